@@ -0,0 +1,47 @@
+//! Measures the cost of matching a topic against a `SubscriptionTable`, for table sizes typical
+//! on embedded devices and beyond.
+//!
+//! Run with `cargo bench --bench subscriptions`. This uses no benchmark harness, so it only
+//! reports the mean time per match.
+
+use embmq::packet::{qos::QoS, subscribe::SubscriptionOptions};
+use embmq::subscriptions::SubscriptionTable;
+use std::hint::black_box;
+use std::time::Instant;
+
+const ITERATIONS: u32 = 100_000;
+
+fn bench<const N: usize>() {
+    let mut table = SubscriptionTable::<u16, N, 64>::new();
+    let options = SubscriptionOptions::new(QoS::AtMostOnce);
+    for i in 0..N {
+        // A mix of exact, single-level and multi-level wildcard filters, one of which matches.
+        let filter = match i % 3 {
+            0 => format!("site/{i}/sensors/temperature"),
+            1 => format!("site/{i}/+/humidity"),
+            _ => format!("site/{i}/#"),
+        };
+        table.subscribe(i as u16, &filter, options).unwrap();
+    }
+
+    let topic = "site/1/sensors/humidity";
+    let start = Instant::now();
+    let mut matched = 0;
+    for _ in 0..ITERATIONS {
+        matched += black_box(&table).matching(black_box(topic)).count();
+    }
+    let elapsed = start.elapsed();
+    assert_eq!(matched, ITERATIONS as usize);
+
+    println!(
+        "matching against {N:>4} subscriptions: {:>8.1} ns",
+        elapsed.as_nanos() as f64 / f64::from(ITERATIONS)
+    );
+}
+
+fn main() {
+    bench::<8>();
+    bench::<32>();
+    bench::<128>();
+    bench::<512>();
+}
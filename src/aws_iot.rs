@@ -0,0 +1,163 @@
+//! This module contains helpers for connecting to AWS IoT Core and staying within its limits.
+
+use crate::buf_writer::BufWriter;
+pub use crate::buf_writer::BufferTooSmall;
+
+/// ALPN protocol for connecting with X.509 client certificates on port 443.
+pub const ALPN_X509: &str = "x-amzn-mqtt-ca";
+/// ALPN protocol for connecting through a custom authorizer on port 443.
+pub const ALPN_CUSTOM_AUTHORIZER: &str = "mqtt";
+/// Port for MQTT over TLS when ALPN is used.
+pub const PORT_ALPN: u16 = 443;
+/// Port for MQTT over TLS without ALPN, only usable with X.509 client certificates.
+pub const PORT: u16 = 8883;
+
+/// Largest packet AWS IoT Core accepts, in bytes.
+pub const MAX_PACKET_SIZE: u32 = 128 * 1024;
+/// Largest topic name or filter AWS IoT Core accepts, in bytes.
+pub const MAX_TOPIC_LENGTH: usize = 256;
+/// Largest number of `/` in a topic name or filter AWS IoT Core accepts.
+pub const MAX_TOPIC_SLASHES: usize = 7;
+/// Largest client identifier AWS IoT Core accepts, in bytes.
+pub const MAX_CLIENT_ID_LENGTH: usize = 128;
+
+/// Reasons for a topic being rejected by AWS IoT Core.
+#[derive(Debug, PartialEq, Eq)]
+pub enum TopicLimitError {
+    TooLong,
+    TooManyLevels,
+}
+
+/// Check a topic name or filter against the AWS IoT Core limits, in addition to the general
+/// validation in [`crate::topic`].
+///
+/// Note that the mandatory prefix of Basic Ingest topics (`$aws/rules/{rule}/`) does not count
+/// towards the limits, so it should be stripped before calling this function.
+pub fn check_topic_limits(topic: &str) -> Result<(), TopicLimitError> {
+    if topic.len() > MAX_TOPIC_LENGTH {
+        return Err(TopicLimitError::TooLong);
+    }
+    if topic.bytes().filter(|&b| b == b'/').count() > MAX_TOPIC_SLASHES {
+        return Err(TopicLimitError::TooManyLevels);
+    }
+
+    Ok(())
+}
+
+/// Parameters for authenticating through a custom authorizer.
+#[derive(Debug)]
+pub struct CustomAuthorizer<'a> {
+    /// Name of the authorizer to invoke.
+    pub name: &'a str,
+    /// Query parameter name and value of the token, if the authorizer expects one.
+    pub token: Option<(&'a str, &'a str)>,
+    /// Signature of the token, if token signing is enabled for the authorizer.
+    pub signature: Option<&'a str>,
+}
+
+impl CustomAuthorizer<'_> {
+    /// Build the MQTT username, encoding the authorizer parameters as a query following the
+    /// given username, e.g. `device?x-amz-customauthorizer-name=my-authorizer`.
+    ///
+    /// Parameter values are percent-encoded. Returns the part of `buf` holding the username.
+    pub fn username<'b>(
+        &self,
+        username: &str,
+        buf: &'b mut [u8],
+    ) -> Result<&'b str, BufferTooSmall> {
+        let mut writer = BufWriter::new(buf);
+
+        writer.push_percent_encoded(username.as_bytes())?;
+        writer.push_byte(b'?')?;
+        push_param(&mut writer, "x-amz-customauthorizer-name", self.name)?;
+        if let Some(signature) = self.signature {
+            writer.push_byte(b'&')?;
+            push_param(&mut writer, "x-amz-customauthorizer-signature", signature)?;
+        }
+        if let Some((key, value)) = self.token {
+            writer.push_byte(b'&')?;
+            push_param(&mut writer, key, value)?;
+        }
+
+        Ok(writer.into_str())
+    }
+}
+
+fn push_param(writer: &mut BufWriter, key: &str, value: &str) -> Result<(), BufferTooSmall> {
+    writer.push_percent_encoded(key.as_bytes())?;
+    writer.push_byte(b'=')?;
+    writer.push_percent_encoded(value.as_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_check_topic_limits() {
+        assert_eq!(check_topic_limits("a/b/c/d/e/f/g/h"), Ok(()));
+        assert_eq!(
+            check_topic_limits("a/b/c/d/e/f/g/h/i"),
+            Err(TopicLimitError::TooManyLevels)
+        );
+        assert_eq!(check_topic_limits(&"a".repeat(256)), Ok(()));
+        assert_eq!(
+            check_topic_limits(&"a".repeat(257)),
+            Err(TopicLimitError::TooLong)
+        );
+    }
+
+    #[test]
+    fn test_custom_authorizer_username_name_only() {
+        let authorizer = CustomAuthorizer {
+            name: "my-authorizer",
+            token: None,
+            signature: None,
+        };
+        let mut buf = [0u8; 128];
+        assert_eq!(
+            authorizer.username("device", &mut buf),
+            Ok("device?x-amz-customauthorizer-name=my-authorizer")
+        );
+    }
+
+    #[test]
+    fn test_custom_authorizer_username_signed_token() {
+        let authorizer = CustomAuthorizer {
+            name: "auth",
+            token: Some(("token", "abc")),
+            signature: Some("c2ln+/="),
+        };
+        let mut buf = [0u8; 128];
+        assert_eq!(
+            authorizer.username("", &mut buf),
+            Ok("?x-amz-customauthorizer-name=auth\
+                &x-amz-customauthorizer-signature=c2ln%2B%2F%3D&token=abc")
+        );
+    }
+
+    #[test]
+    fn test_custom_authorizer_username_encodes_username() {
+        let authorizer = CustomAuthorizer {
+            name: "auth",
+            token: None,
+            signature: None,
+        };
+        let mut buf = [0u8; 128];
+        assert_eq!(
+            authorizer.username("a b?", &mut buf),
+            Ok("a%20b%3F?x-amz-customauthorizer-name=auth")
+        );
+    }
+
+    #[test]
+    fn test_custom_authorizer_username_buffer_too_small() {
+        let authorizer = CustomAuthorizer {
+            name: "auth",
+            token: None,
+            signature: None,
+        };
+        let mut buf = [0u8; 10];
+        assert_eq!(authorizer.username("device", &mut buf), Err(BufferTooSmall));
+    }
+}
@@ -0,0 +1,402 @@
+//! This module contains helpers for connecting devices to Azure IoT Hub.
+
+use crate::buf_writer::BufWriter;
+pub use crate::buf_writer::BufferTooSmall;
+
+/// IoT Hub API version the username and topics follow.
+pub const API_VERSION: &str = "2021-04-12";
+/// Port for MQTT over TLS.
+pub const PORT: u16 = 8883;
+
+/// Subscribe to this filter to receive twin responses.
+pub const TWIN_RESPONSE_FILTER: &str = "$iothub/twin/res/#";
+/// Subscribe to this filter to receive desired property updates.
+pub const TWIN_DESIRED_PATCH_FILTER: &str = "$iothub/twin/PATCH/properties/desired/#";
+/// Subscribe to this filter to receive direct method invocations.
+pub const DIRECT_METHOD_FILTER: &str = "$iothub/methods/POST/#";
+/// Publish to this topic, followed by a request id, to get the twin.
+pub const TWIN_GET_TOPIC_PREFIX: &str = "$iothub/twin/GET/?$rid=";
+/// Publish to this topic, followed by a request id, to update reported properties.
+pub const TWIN_REPORTED_PATCH_TOPIC_PREFIX: &str = "$iothub/twin/PATCH/properties/reported/?$rid=";
+/// Publish to this topic, followed by `{status}/?$rid={request id}`, to answer a direct method.
+pub const DIRECT_METHOD_RESPONSE_TOPIC_PREFIX: &str = "$iothub/methods/res/";
+
+/// Largest decoded device key supported, in bytes. IoT Hub generates 32 byte keys.
+const MAX_KEY_LEN: usize = 64;
+
+/// Computes HMAC-SHA256, provided by the application from its crypto library or hardware
+/// accelerator.
+pub trait HmacSha256 {
+    /// Compute the MAC of the concatenation of the message parts.
+    fn hmac_sha256(&mut self, key: &[u8], message: &[&[u8]]) -> [u8; 32];
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum SasTokenError {
+    /// The device key is not valid base64, or longer than 64 bytes.
+    InvalidKey,
+    BufferTooSmall,
+}
+
+impl From<BufferTooSmall> for SasTokenError {
+    fn from(_: BufferTooSmall) -> Self {
+        SasTokenError::BufferTooSmall
+    }
+}
+
+/// Identity of a device, or a module on a device, registered with an IoT Hub.
+#[derive(Debug)]
+pub struct Device<'a> {
+    /// Host name of the IoT Hub, e.g. `my-hub.azure-devices.net`.
+    pub hub: &'a str,
+    pub device_id: &'a str,
+    pub module_id: Option<&'a str>,
+}
+
+impl Device<'_> {
+    /// The MQTT client identifier, `{device_id}` or `{device_id}/{module_id}`.
+    pub fn client_id<'b>(&self, buf: &'b mut [u8]) -> Result<&'b str, BufferTooSmall> {
+        let mut writer = BufWriter::new(buf);
+        self.push_identity(&mut writer)?;
+        Ok(writer.into_str())
+    }
+
+    /// The MQTT username, `{hub}/{device_id}/?api-version=...`, with the module id following
+    /// the device id for modules.
+    pub fn username<'b>(&self, buf: &'b mut [u8]) -> Result<&'b str, BufferTooSmall> {
+        let mut writer = BufWriter::new(buf);
+        writer.push_str(self.hub)?;
+        writer.push_byte(b'/')?;
+        self.push_identity(&mut writer)?;
+        writer.push_str("/?api-version=")?;
+        writer.push_str(API_VERSION)?;
+        Ok(writer.into_str())
+    }
+
+    /// The topic to publish telemetry to.
+    pub fn telemetry_topic<'b>(&self, buf: &'b mut [u8]) -> Result<&'b str, BufferTooSmall> {
+        let mut writer = BufWriter::new(buf);
+        self.push_path(&mut writer)?;
+        writer.push_str("/messages/events/")?;
+        Ok(writer.into_str())
+    }
+
+    /// The filter to subscribe to for cloud-to-device messages. Not available for modules.
+    pub fn cloud_to_device_filter<'b>(&self, buf: &'b mut [u8]) -> Result<&'b str, BufferTooSmall> {
+        let mut writer = BufWriter::new(buf);
+        writer.push_str("devices/")?;
+        writer.push_str(self.device_id)?;
+        writer.push_str("/messages/devicebound/#")?;
+        Ok(writer.into_str())
+    }
+
+    /// Build a shared access signature token to use as the MQTT password.
+    ///
+    /// `key` is the base64 encoded device key and `expiry` the Unix time in seconds after which
+    /// the token is no longer accepted.
+    pub fn sas_token<'b, H: HmacSha256>(
+        &self,
+        key: &str,
+        expiry: u64,
+        hmac: &mut H,
+        buf: &'b mut [u8],
+    ) -> Result<&'b str, SasTokenError> {
+        let mut key_buf = [0u8; MAX_KEY_LEN];
+        let key = base64_decode(key, &mut key_buf).ok_or(SasTokenError::InvalidKey)?;
+
+        let mut writer = BufWriter::new(buf);
+        writer.push_str("SharedAccessSignature sr=")?;
+        // The resource URI, `{hub}/{path}`, is encoded straight into the token.
+        let encoded_uri_start = writer.len();
+        writer.push_percent_encoded(self.hub.as_bytes())?;
+        writer.push_percent_encoded(b"/")?;
+        for part in self.path_parts() {
+            writer.push_percent_encoded(part.as_bytes())?;
+        }
+
+        let mut expiry_buf = [0u8; 20];
+        let mut expiry_writer = BufWriter::new(&mut expiry_buf);
+        expiry_writer.push_u64(expiry)?;
+        let expiry = expiry_writer.written(0);
+
+        // The string to sign is the encoded resource URI and the expiry, separated by a newline.
+        let signature = hmac.hmac_sha256(key, &[writer.written(encoded_uri_start), b"\n", expiry]);
+        let mut signature_buf = [0u8; 44];
+        let signature = base64_encode(&signature, &mut signature_buf);
+
+        writer.push_str("&sig=")?;
+        writer.push_percent_encoded(signature)?;
+        writer.push_str("&se=")?;
+        writer.push_bytes(expiry)?;
+        Ok(writer.into_str())
+    }
+
+    /// `devices/{device_id}`, followed by `/modules/{module_id}` for modules.
+    fn push_path(&self, writer: &mut BufWriter) -> Result<(), BufferTooSmall> {
+        for part in self.path_parts() {
+            writer.push_str(part)?;
+        }
+        Ok(())
+    }
+
+    /// The parts of the path, some of which may be empty.
+    fn path_parts(&self) -> [&str; 4] {
+        match self.module_id {
+            Some(module_id) => ["devices/", self.device_id, "/modules/", module_id],
+            None => ["devices/", self.device_id, "", ""],
+        }
+    }
+
+    fn push_identity(&self, writer: &mut BufWriter) -> Result<(), BufferTooSmall> {
+        writer.push_str(self.device_id)?;
+        if let Some(module_id) = self.module_id {
+            writer.push_byte(b'/')?;
+            writer.push_str(module_id)?;
+        }
+        Ok(())
+    }
+}
+
+const BASE64_ALPHABET: &[u8; 64] =
+    b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+/// Encode the given bytes as padded base64, `out` must hold `4 * input.len().div_ceil(3)` bytes.
+fn base64_encode<'b>(input: &[u8], out: &'b mut [u8]) -> &'b [u8] {
+    let mut len = 0;
+    for chunk in input.chunks(3) {
+        let bytes = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let sextets = [
+            bytes[0] >> 2,
+            ((bytes[0] & 0b11) << 4) | (bytes[1] >> 4),
+            ((bytes[1] & 0b1111) << 2) | (bytes[2] >> 6),
+            bytes[2] & 0b11_1111,
+        ];
+        for (i, sextet) in sextets.into_iter().enumerate() {
+            // A chunk of n bytes is encoded in n + 1 characters, the rest is padding.
+            out[len] = match i <= chunk.len() {
+                true => BASE64_ALPHABET[usize::from(sextet)],
+                false => b'=',
+            };
+            len += 1;
+        }
+    }
+    &out[..len]
+}
+
+/// Decode padded base64, returning `None` if the input is invalid or doesn't fit into `out`.
+fn base64_decode<'b>(input: &str, out: &'b mut [u8]) -> Option<&'b [u8]> {
+    let input = input.as_bytes();
+    if !input.len().is_multiple_of(4) {
+        return None;
+    }
+
+    let mut len = 0;
+    let chunk_count = input.len() / 4;
+    for (chunk_index, chunk) in input.chunks(4).enumerate() {
+        let padding = chunk.iter().rev().take_while(|&&c| c == b'=').count();
+        if padding > 2 || (padding > 0 && chunk_index != chunk_count - 1) {
+            return None;
+        }
+
+        let mut value = 0u32;
+        for &c in &chunk[..4 - padding] {
+            let sextet = BASE64_ALPHABET.iter().position(|&a| a == c)?;
+            value = (value << 6) | sextet as u32;
+        }
+        value <<= 6 * padding;
+
+        let bytes = value.to_be_bytes();
+        for &byte in &bytes[1..4 - padding] {
+            *out.get_mut(len)? = byte;
+            len += 1;
+        }
+    }
+    Some(&out[..len])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEVICE: Device = Device {
+        hub: "my-hub.azure-devices.net",
+        device_id: "sensor-1",
+        module_id: None,
+    };
+
+    const MODULE: Device = Device {
+        hub: "my-hub.azure-devices.net",
+        device_id: "sensor-1",
+        module_id: Some("filter"),
+    };
+
+    /// Records the key and message and returns a fixed MAC.
+    struct RecordingHmac {
+        key: [u8; MAX_KEY_LEN],
+        key_len: usize,
+        message: [u8; 1024],
+        message_len: usize,
+    }
+
+    impl HmacSha256 for RecordingHmac {
+        fn hmac_sha256(&mut self, key: &[u8], message: &[&[u8]]) -> [u8; 32] {
+            self.key[..key.len()].copy_from_slice(key);
+            self.key_len = key.len();
+            for part in message {
+                self.message[self.message_len..self.message_len + part.len()].copy_from_slice(part);
+                self.message_len += part.len();
+            }
+            [0xFB; 32]
+        }
+    }
+
+    #[test]
+    fn test_client_id() {
+        let mut buf = [0u8; 64];
+        assert_eq!(DEVICE.client_id(&mut buf), Ok("sensor-1"));
+        assert_eq!(MODULE.client_id(&mut buf), Ok("sensor-1/filter"));
+    }
+
+    #[test]
+    fn test_username() {
+        let mut buf = [0u8; 128];
+        assert_eq!(
+            DEVICE.username(&mut buf),
+            Ok("my-hub.azure-devices.net/sensor-1/?api-version=2021-04-12")
+        );
+        assert_eq!(
+            MODULE.username(&mut buf),
+            Ok("my-hub.azure-devices.net/sensor-1/filter/?api-version=2021-04-12")
+        );
+
+        let mut buf = [0u8; 16];
+        assert_eq!(DEVICE.username(&mut buf), Err(BufferTooSmall));
+    }
+
+    #[test]
+    fn test_topics() {
+        let mut buf = [0u8; 128];
+        assert_eq!(
+            DEVICE.telemetry_topic(&mut buf),
+            Ok("devices/sensor-1/messages/events/")
+        );
+        assert_eq!(
+            MODULE.telemetry_topic(&mut buf),
+            Ok("devices/sensor-1/modules/filter/messages/events/")
+        );
+        assert_eq!(
+            DEVICE.cloud_to_device_filter(&mut buf),
+            Ok("devices/sensor-1/messages/devicebound/#")
+        );
+    }
+
+    #[test]
+    fn test_sas_token() {
+        let mut hmac = RecordingHmac {
+            key: [0; MAX_KEY_LEN],
+            key_len: 0,
+            message: [0; 1024],
+            message_len: 0,
+        };
+        let mut buf = [0u8; 256];
+
+        let token = DEVICE
+            .sas_token("AAECAw==", 1700000000, &mut hmac, &mut buf)
+            .unwrap();
+        assert_eq!(
+            token,
+            "SharedAccessSignature sr=my-hub.azure-devices.net%2Fdevices%2Fsensor-1\
+             &sig=%2B%2Fv7%2B%2Fv7%2B%2Fv7%2B%2Fv7%2B%2Fv7%2B%2Fv7%2B%2Fv7%2B%2Fv7%2B%2Fv7%2B%2Fv7%2B%2Fs%3D\
+             &se=1700000000"
+        );
+        assert_eq!(hmac.key[..hmac.key_len], [0, 1, 2, 3]);
+        assert_eq!(
+            hmac.message[..hmac.message_len],
+            *b"my-hub.azure-devices.net%2Fdevices%2Fsensor-1\n1700000000"
+        );
+    }
+
+    #[test]
+    fn test_sas_token_resource_uri() {
+        let mut hmac = RecordingHmac {
+            key: [0; MAX_KEY_LEN],
+            key_len: 0,
+            message: [0; 1024],
+            message_len: 0,
+        };
+        let mut buf = [0u8; 256];
+        MODULE
+            .sas_token("AAECAw==", 1, &mut hmac, &mut buf)
+            .unwrap();
+        assert_eq!(
+            hmac.message[..hmac.message_len],
+            *b"my-hub.azure-devices.net%2Fdevices%2Fsensor-1%2Fmodules%2Ffilter\n1"
+        );
+
+        // The length of the resource URI is only limited by the token buffer.
+        let hub = "h".repeat(600);
+        let device = Device {
+            hub: &hub,
+            ..DEVICE
+        };
+        hmac.message_len = 0;
+        let mut buf = [0u8; 1024];
+        let token = device
+            .sas_token("AAECAw==", 1, &mut hmac, &mut buf)
+            .unwrap();
+        assert!(token.starts_with("SharedAccessSignature sr=hhh"));
+        assert!(token.contains("h%2Fdevices%2Fsensor-1&sig="));
+    }
+
+    #[test]
+    fn test_sas_token_errors() {
+        let mut hmac = RecordingHmac {
+            key: [0; MAX_KEY_LEN],
+            key_len: 0,
+            message: [0; 1024],
+            message_len: 0,
+        };
+
+        let mut buf = [0u8; 256];
+        assert_eq!(
+            DEVICE.sas_token("not base64", 0, &mut hmac, &mut buf),
+            Err(SasTokenError::InvalidKey)
+        );
+
+        let mut buf = [0u8; 32];
+        assert_eq!(
+            DEVICE.sas_token("AAECAw==", 0, &mut hmac, &mut buf),
+            Err(SasTokenError::BufferTooSmall)
+        );
+    }
+
+    #[test]
+    fn test_base64_encode() {
+        let mut out = [0u8; 12];
+        assert_eq!(base64_encode(b"", &mut out), b"");
+        assert_eq!(base64_encode(b"f", &mut out), b"Zg==");
+        assert_eq!(base64_encode(b"fo", &mut out), b"Zm8=");
+        assert_eq!(base64_encode(b"foo", &mut out), b"Zm9v");
+        assert_eq!(base64_encode(b"foobar", &mut out), b"Zm9vYmFy");
+        assert_eq!(base64_encode(&[0xFB, 0xFF], &mut out), b"+/8=");
+    }
+
+    #[test]
+    fn test_base64_decode() {
+        let mut out = [0u8; 8];
+        assert_eq!(base64_decode("", &mut out), Some(&b""[..]));
+        assert_eq!(base64_decode("Zg==", &mut out), Some(&b"f"[..]));
+        assert_eq!(base64_decode("Zm8=", &mut out), Some(&b"fo"[..]));
+        assert_eq!(base64_decode("Zm9vYmFy", &mut out), Some(&b"foobar"[..]));
+        assert_eq!(base64_decode("+/8=", &mut out), Some(&[0xFB, 0xFF][..]));
+        assert_eq!(base64_decode("Zm9", &mut out), None);
+        assert_eq!(base64_decode("Zm9-", &mut out), None);
+        assert_eq!(base64_decode("Zg==Zm9v", &mut out), None);
+        assert_eq!(base64_decode("Z===", &mut out), None);
+        assert_eq!(base64_decode("Zm9vYmFyYmF6", &mut out), None);
+    }
+}
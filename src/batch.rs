@@ -0,0 +1,154 @@
+//! This module contains a helper for accumulating small samples into a single payload, so they
+//! can be sent with one publish instead of one publish per sample.
+
+use crate::clock::Clock;
+
+/// Reasons for a sample not being added to a batch.
+#[derive(Debug, PartialEq, Eq)]
+pub enum BatchError {
+    /// The sample doesn't fit into the rest of the batch, take the batch first.
+    Full,
+    /// The sample is larger than an empty batch.
+    SampleTooLarge,
+}
+
+/// Accumulates samples into a payload of up to `N` bytes.
+///
+/// A batch is due once it holds at least `flush_len` bytes or its first sample was added
+/// `max_age_ms` milliseconds ago. Samples are concatenated as given, so any delimiters must be
+/// part of the samples.
+#[derive(Debug)]
+pub struct Batch<C, const N: usize> {
+    clock: C,
+    flush_len: usize,
+    max_age_ms: u64,
+    buf: [u8; N],
+    len: usize,
+    started_at_ms: u64,
+}
+
+impl<C: Clock, const N: usize> Batch<C, N> {
+    /// `flush_len` is clamped to `N`.
+    pub fn new(clock: C, flush_len: usize, max_age_ms: u64) -> Self {
+        Self {
+            clock,
+            flush_len: flush_len.min(N),
+            max_age_ms,
+            buf: [0; N],
+            len: 0,
+            started_at_ms: 0,
+        }
+    }
+
+    pub fn push(&mut self, sample: &[u8]) -> Result<(), BatchError> {
+        if sample.len() > N {
+            return Err(BatchError::SampleTooLarge);
+        }
+        if sample.len() > N - self.len {
+            return Err(BatchError::Full);
+        }
+
+        if self.len == 0 {
+            self.started_at_ms = self.clock.now_ms();
+        }
+        self.buf[self.len..self.len + sample.len()].copy_from_slice(sample);
+        self.len += sample.len();
+        Ok(())
+    }
+
+    /// The time at which the batch becomes due because of its age, if it holds any samples.
+    ///
+    /// Useful for sleeping until either the next sample arrives or the batch has to be sent.
+    pub fn deadline_ms(&self) -> Option<u64> {
+        match self.len {
+            0 => None,
+            _ => Some(self.started_at_ms.saturating_add(self.max_age_ms)),
+        }
+    }
+
+    /// Whether the batch should be sent now.
+    pub fn is_due(&self) -> bool {
+        match self.deadline_ms() {
+            Some(deadline_ms) => self.len >= self.flush_len || self.clock.now_ms() >= deadline_ms,
+            None => false,
+        }
+    }
+
+    /// Take the accumulated payload, leaving the batch empty. Returns `None` if it is empty.
+    pub fn take(&mut self) -> Option<&[u8]> {
+        let len = core::mem::take(&mut self.len);
+        match len {
+            0 => None,
+            _ => Some(&self.buf[..len]),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::cell::Cell;
+
+    struct TestClock(Cell<u64>);
+
+    impl Clock for TestClock {
+        fn now_ms(&self) -> u64 {
+            self.0.get()
+        }
+    }
+
+    #[test]
+    fn test_due_by_size() {
+        let clock = TestClock(Cell::new(0));
+        let mut batch = Batch::<_, 8>::new(&clock, 4, 1000);
+        assert!(!batch.is_due());
+
+        batch.push(b"ab").unwrap();
+        assert!(!batch.is_due());
+        batch.push(b"cd").unwrap();
+        assert!(batch.is_due());
+
+        assert_eq!(batch.take(), Some(&b"abcd"[..]));
+        assert!(batch.is_empty());
+        assert!(!batch.is_due());
+        assert_eq!(batch.take(), None);
+    }
+
+    #[test]
+    fn test_due_by_age() {
+        let clock = TestClock(Cell::new(500));
+        let mut batch = Batch::<_, 8>::new(&clock, 8, 1000);
+        assert_eq!(batch.deadline_ms(), None);
+
+        batch.push(b"a").unwrap();
+        clock.0.set(1000);
+        batch.push(b"b").unwrap();
+        // The age counts from the first sample.
+        assert_eq!(batch.deadline_ms(), Some(1500));
+        assert!(!batch.is_due());
+        clock.0.set(1500);
+        assert!(batch.is_due());
+
+        assert_eq!(batch.take(), Some(&b"ab"[..]));
+        batch.push(b"c").unwrap();
+        assert_eq!(batch.deadline_ms(), Some(2500));
+    }
+
+    #[test]
+    fn test_push_errors() {
+        let clock = TestClock(Cell::new(0));
+        let mut batch = Batch::<_, 4>::new(&clock, 4, 1000);
+        assert_eq!(batch.push(b"abcde"), Err(BatchError::SampleTooLarge));
+        batch.push(b"abc").unwrap();
+        assert_eq!(batch.push(b"de"), Err(BatchError::Full));
+        assert_eq!(batch.len(), 3);
+    }
+}
@@ -0,0 +1,125 @@
+//! This module contains a writer for assembling strings in a caller-provided buffer, for the
+//! helpers that build usernames, tokens and topics without allocating.
+
+/// The given buffer is too small to hold the result.
+#[derive(Debug, PartialEq, Eq)]
+pub struct BufferTooSmall;
+
+pub(crate) struct BufWriter<'b> {
+    buf: &'b mut [u8],
+    len: usize,
+}
+
+// Not every helper is used with every combination of features.
+#[allow(dead_code)]
+impl<'b> BufWriter<'b> {
+    pub(crate) fn new(buf: &'b mut [u8]) -> Self {
+        Self { buf, len: 0 }
+    }
+
+    pub(crate) fn len(&self) -> usize {
+        self.len
+    }
+
+    /// The bytes written so far, starting at the given offset.
+    pub(crate) fn written(&self, from: usize) -> &[u8] {
+        &self.buf[from..self.len]
+    }
+
+    pub(crate) fn push_byte(&mut self, byte: u8) -> Result<(), BufferTooSmall> {
+        let slot = self.buf.get_mut(self.len).ok_or(BufferTooSmall)?;
+        *slot = byte;
+        self.len += 1;
+        Ok(())
+    }
+
+    pub(crate) fn push_bytes(&mut self, bytes: &[u8]) -> Result<(), BufferTooSmall> {
+        let slot = self
+            .buf
+            .get_mut(self.len..self.len + bytes.len())
+            .ok_or(BufferTooSmall)?;
+        slot.copy_from_slice(bytes);
+        self.len += bytes.len();
+        Ok(())
+    }
+
+    pub(crate) fn push_str(&mut self, s: &str) -> Result<(), BufferTooSmall> {
+        self.push_bytes(s.as_bytes())
+    }
+
+    /// Push the decimal representation of the given number.
+    pub(crate) fn push_u64(&mut self, mut num: u64) -> Result<(), BufferTooSmall> {
+        let mut digits = [0u8; 20];
+        let mut start = digits.len();
+        loop {
+            start -= 1;
+            digits[start] = b'0' + (num % 10) as u8;
+            num /= 10;
+            if num == 0 {
+                break;
+            }
+        }
+        self.push_bytes(&digits[start..])
+    }
+
+    /// Push the given bytes, percent-encoding everything but unreserved URI characters.
+    pub(crate) fn push_percent_encoded(&mut self, bytes: &[u8]) -> Result<(), BufferTooSmall> {
+        const HEX: &[u8; 16] = b"0123456789ABCDEF";
+
+        for &byte in bytes {
+            if byte.is_ascii_alphanumeric() || matches!(byte, b'-' | b'.' | b'_' | b'~') {
+                self.push_byte(byte)?;
+            } else {
+                self.push_bytes(&[
+                    b'%',
+                    HEX[usize::from(byte >> 4)],
+                    HEX[usize::from(byte & 0x0F)],
+                ])?;
+            }
+        }
+        Ok(())
+    }
+
+    pub(crate) fn into_bytes(self) -> &'b [u8] {
+        &self.buf[..self.len]
+    }
+
+    pub(crate) fn into_str(self) -> &'b str {
+        core::str::from_utf8(self.into_bytes()).expect("Only UTF-8 is written")
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push() {
+        let mut buf = [0u8; 32];
+        let mut writer = BufWriter::new(&mut buf);
+        writer.push_str("a/").unwrap();
+        writer.push_u64(0).unwrap();
+        writer.push_byte(b'/').unwrap();
+        writer.push_u64(u64::MAX).unwrap();
+        assert_eq!(writer.into_str(), "a/0/18446744073709551615");
+    }
+
+    #[test]
+    fn test_push_percent_encoded() {
+        let mut buf = [0u8; 32];
+        let mut writer = BufWriter::new(&mut buf);
+        writer.push_percent_encoded(b"aZ9-._~ +/=\xC3\xA4").unwrap();
+        assert_eq!(writer.into_str(), "aZ9-._~%20%2B%2F%3D%C3%A4");
+    }
+
+    #[test]
+    fn test_buffer_too_small() {
+        let mut buf = [0u8; 2];
+        let mut writer = BufWriter::new(&mut buf);
+        writer.push_str("ab").unwrap();
+        assert_eq!(writer.push_byte(b'c'), Err(BufferTooSmall));
+        assert_eq!(writer.push_str("c"), Err(BufferTooSmall));
+        assert_eq!(writer.push_percent_encoded(b" "), Err(BufferTooSmall));
+        assert_eq!(writer.into_str(), "ab");
+    }
+}
@@ -0,0 +1,173 @@
+//! This module contains a bounded table mirroring the most recent payload received on each topic,
+//! so application logic can read the current value of a topic at any time.
+
+/// Reasons for a received message not being stored.
+#[derive(Debug, PartialEq, Eq)]
+pub enum CacheError {
+    /// All entries are used by other topics.
+    Full,
+    TopicTooLong,
+    PayloadTooLarge,
+}
+
+#[derive(Debug)]
+struct Entry<const TOPIC_LEN: usize, const PAYLOAD_LEN: usize> {
+    topic: [u8; TOPIC_LEN],
+    topic_len: usize,
+    payload: [u8; PAYLOAD_LEN],
+    payload_len: usize,
+}
+
+impl<const TOPIC_LEN: usize, const PAYLOAD_LEN: usize> Entry<TOPIC_LEN, PAYLOAD_LEN> {
+    fn topic(&self) -> &str {
+        core::str::from_utf8(&self.topic[..self.topic_len]).expect("Topics are stored from str")
+    }
+}
+
+/// Last value cache for up to `N` topics of up to `TOPIC_LEN` bytes, each with a payload of up to
+/// `PAYLOAD_LEN` bytes.
+///
+/// Feed every received message into [`LastValueCache::update`]. As with retained messages, an
+/// empty payload removes the topic.
+#[derive(Debug)]
+pub struct LastValueCache<const N: usize, const TOPIC_LEN: usize, const PAYLOAD_LEN: usize> {
+    entries: [Option<Entry<TOPIC_LEN, PAYLOAD_LEN>>; N],
+}
+
+impl<const N: usize, const TOPIC_LEN: usize, const PAYLOAD_LEN: usize>
+    LastValueCache<N, TOPIC_LEN, PAYLOAD_LEN>
+{
+    pub fn new() -> Self {
+        Self {
+            entries: [const { None }; N],
+        }
+    }
+
+    /// Store the payload as the current value of the topic, replacing the previous value.
+    pub fn update(&mut self, topic: &str, payload: &[u8]) -> Result<(), CacheError> {
+        if topic.len() > TOPIC_LEN {
+            return Err(CacheError::TopicTooLong);
+        }
+        if payload.len() > PAYLOAD_LEN {
+            return Err(CacheError::PayloadTooLarge);
+        }
+
+        let existing = self
+            .entries
+            .iter()
+            .position(|entry| entry.as_ref().is_some_and(|entry| entry.topic() == topic));
+        if payload.is_empty() {
+            if let Some(index) = existing {
+                self.entries[index] = None;
+            }
+            return Ok(());
+        }
+
+        let index = existing
+            .or_else(|| self.entries.iter().position(Option::is_none))
+            .ok_or(CacheError::Full)?;
+        let entry = self.entries[index].get_or_insert(Entry {
+            topic: [0; TOPIC_LEN],
+            topic_len: 0,
+            payload: [0; PAYLOAD_LEN],
+            payload_len: 0,
+        });
+        entry.topic[..topic.len()].copy_from_slice(topic.as_bytes());
+        entry.topic_len = topic.len();
+        entry.payload[..payload.len()].copy_from_slice(payload);
+        entry.payload_len = payload.len();
+        Ok(())
+    }
+
+    /// The most recent payload received on the topic.
+    pub fn get(&self, topic: &str) -> Option<&[u8]> {
+        self.iter()
+            .find(|(entry_topic, _)| *entry_topic == topic)
+            .map(|(_, payload)| payload)
+    }
+
+    /// Remove the topic, e.g. after unsubscribing from it.
+    pub fn remove(&mut self, topic: &str) {
+        for entry in &mut self.entries {
+            if entry.as_ref().is_some_and(|entry| entry.topic() == topic) {
+                *entry = None;
+            }
+        }
+    }
+
+    /// Iterate over all topics and their most recent payloads.
+    pub fn iter(&self) -> impl Iterator<Item = (&str, &[u8])> {
+        self.entries
+            .iter()
+            .flatten()
+            .map(|entry| (entry.topic(), &entry.payload[..entry.payload_len]))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.iter().flatten().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl<const N: usize, const TOPIC_LEN: usize, const PAYLOAD_LEN: usize> Default
+    for LastValueCache<N, TOPIC_LEN, PAYLOAD_LEN>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_update_and_get() {
+        let mut cache = LastValueCache::<2, 16, 8>::new();
+        assert!(cache.is_empty());
+        assert_eq!(cache.get("a/b"), None);
+
+        cache.update("a/b", b"1").unwrap();
+        cache.update("c", b"on").unwrap();
+        cache.update("a/b", b"22").unwrap();
+        assert_eq!(cache.get("a/b"), Some(&b"22"[..]));
+        assert_eq!(cache.get("c"), Some(&b"on"[..]));
+        assert_eq!(cache.len(), 2);
+    }
+
+    #[test]
+    fn test_empty_payload_removes_topic() {
+        let mut cache = LastValueCache::<2, 16, 8>::new();
+        cache.update("a", b"1").unwrap();
+        cache.update("a", b"").unwrap();
+        assert_eq!(cache.get("a"), None);
+        assert!(cache.is_empty());
+
+        // Removing a topic that isn't cached needs no free entry.
+        cache.update("b", b"1").unwrap();
+        cache.update("c", b"1").unwrap();
+        assert_eq!(cache.update("d", b""), Ok(()));
+    }
+
+    #[test]
+    fn test_remove() {
+        let mut cache = LastValueCache::<2, 16, 8>::new();
+        cache.update("a", b"1").unwrap();
+        cache.update("b", b"2").unwrap();
+        cache.remove("a");
+        assert_eq!(cache.iter().collect::<Vec<_>>(), [("b", &b"2"[..])]);
+    }
+
+    #[test]
+    fn test_errors() {
+        let mut cache = LastValueCache::<1, 4, 2>::new();
+        assert_eq!(cache.update("abcde", b"1"), Err(CacheError::TopicTooLong));
+        assert_eq!(cache.update("a", b"123"), Err(CacheError::PayloadTooLarge));
+        cache.update("a", b"1").unwrap();
+        assert_eq!(cache.update("b", b"1"), Err(CacheError::Full));
+        assert_eq!(cache.get("a"), Some(&b"1"[..]));
+    }
+}
@@ -0,0 +1,13 @@
+//! This module contains the abstraction over the time source used by time-based helpers.
+
+/// A monotonic clock, e.g. backed by a hardware timer or `embassy_time::Instant`.
+pub trait Clock {
+    /// Milliseconds elapsed since an arbitrary, fixed point in time.
+    fn now_ms(&self) -> u64;
+}
+
+impl<C: Clock + ?Sized> Clock for &C {
+    fn now_ms(&self) -> u64 {
+        (**self).now_ms()
+    }
+}
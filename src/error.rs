@@ -1,3 +1,5 @@
+use core::convert::Infallible;
+
 use embedded_io_async::ReadExactError;
 
 #[derive(Debug)]
@@ -15,3 +17,13 @@ impl<E> From<ReadExactError<E>> for Error<E> {
         }
     }
 }
+
+impl Error<Infallible> {
+    /// Convert the error of an IO-independent function into the error of any IO-dependent one.
+    pub(crate) fn widen<E>(self) -> Error<E> {
+        match self {
+            Error::MalformedPacket => Error::MalformedPacket,
+            Error::NetworkError(never) => match never {},
+        }
+    }
+}
@@ -0,0 +1,332 @@
+//! This module contains helpers for announcing entities through Home Assistant MQTT discovery.
+//!
+//! Each entity is announced by publishing a retained JSON config payload to
+//! `{discovery_prefix}/{component}/{node_id}/{object_id}/config`, after which Home Assistant
+//! reads its state from, and sends commands to, the topics given in the config.
+
+use crate::buf_writer::BufWriter;
+pub use crate::buf_writer::BufferTooSmall;
+
+/// The discovery prefix Home Assistant uses unless configured otherwise.
+pub const DEFAULT_DISCOVERY_PREFIX: &str = "homeassistant";
+
+/// Reasons for failing to build a discovery topic.
+#[derive(Debug, PartialEq, Eq)]
+pub enum ConfigTopicError {
+    /// The node id or object id is empty or contains characters other than `[a-zA-Z0-9_-]`.
+    InvalidId,
+    BufferTooSmall,
+}
+
+impl From<BufferTooSmall> for ConfigTopicError {
+    fn from(_: BufferTooSmall) -> Self {
+        ConfigTopicError::BufferTooSmall
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Component {
+    Sensor,
+    BinarySensor,
+    Switch,
+}
+
+impl Component {
+    /// The component name used in discovery topics.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Component::Sensor => "sensor",
+            Component::BinarySensor => "binary_sensor",
+            Component::Switch => "switch",
+        }
+    }
+}
+
+/// The device entities belong to, shown as one device in Home Assistant.
+#[derive(Debug)]
+pub struct Device<'a> {
+    /// Unique identifier of the device, e.g. its serial number or MAC address. Also used as
+    /// prefix for the unique ids of its entities.
+    pub identifier: &'a str,
+    pub name: &'a str,
+    pub manufacturer: Option<&'a str>,
+    pub model: Option<&'a str>,
+    pub sw_version: Option<&'a str>,
+}
+
+#[derive(Debug)]
+pub struct Entity<'a> {
+    pub component: Component,
+    /// Identifies the entity within the device, only `[a-zA-Z0-9_-]` are allowed, see
+    /// [`Entity::config_topic`].
+    pub object_id: &'a str,
+    pub name: &'a str,
+    pub state_topic: &'a str,
+    /// Topic Home Assistant publishes commands to, required for switches.
+    pub command_topic: Option<&'a str>,
+    /// Topic the device publishes `online` and `offline` to, usually through its will message.
+    pub availability_topic: Option<&'a str>,
+    pub device_class: Option<&'a str>,
+    pub unit_of_measurement: Option<&'a str>,
+}
+
+impl<'a> Entity<'a> {
+    pub fn sensor(object_id: &'a str, name: &'a str, state_topic: &'a str) -> Self {
+        Self::new(Component::Sensor, object_id, name, state_topic, None)
+    }
+
+    pub fn binary_sensor(object_id: &'a str, name: &'a str, state_topic: &'a str) -> Self {
+        Self::new(Component::BinarySensor, object_id, name, state_topic, None)
+    }
+
+    pub fn switch(
+        object_id: &'a str,
+        name: &'a str,
+        state_topic: &'a str,
+        command_topic: &'a str,
+    ) -> Self {
+        Self::new(
+            Component::Switch,
+            object_id,
+            name,
+            state_topic,
+            Some(command_topic),
+        )
+    }
+
+    fn new(
+        component: Component,
+        object_id: &'a str,
+        name: &'a str,
+        state_topic: &'a str,
+        command_topic: Option<&'a str>,
+    ) -> Self {
+        Self {
+            component,
+            object_id,
+            name,
+            state_topic,
+            command_topic,
+            availability_topic: None,
+            device_class: None,
+            unit_of_measurement: None,
+        }
+    }
+
+    /// The topic to publish the config payload to,
+    /// `{discovery_prefix}/{component}/{node_id}/{object_id}/config`.
+    ///
+    /// Both `node_id` and the object id may only contain `[a-zA-Z0-9_-]`.
+    pub fn config_topic<'b>(
+        &self,
+        discovery_prefix: &str,
+        node_id: &str,
+        buf: &'b mut [u8],
+    ) -> Result<&'b str, ConfigTopicError> {
+        if !is_valid_id(node_id) || !is_valid_id(self.object_id) {
+            return Err(ConfigTopicError::InvalidId);
+        }
+
+        let mut writer = BufWriter::new(buf);
+        for part in [
+            discovery_prefix,
+            self.component.as_str(),
+            node_id,
+            self.object_id,
+        ] {
+            writer.push_str(part)?;
+            writer.push_byte(b'/')?;
+        }
+        writer.push_str("config")?;
+        Ok(writer.into_str())
+    }
+
+    /// The JSON config payload announcing the entity as part of the given device.
+    ///
+    /// The unique id of the entity is `{device identifier}_{object_id}`.
+    pub fn config_payload<'b>(
+        &self,
+        device: &Device,
+        buf: &'b mut [u8],
+    ) -> Result<&'b str, BufferTooSmall> {
+        let mut writer = BufWriter::new(buf);
+        writer.push_byte(b'{')?;
+        push_field(&mut writer, "name", self.name)?;
+        writer.push_str(",\"unique_id\":\"")?;
+        push_json_escaped(&mut writer, device.identifier)?;
+        writer.push_byte(b'_')?;
+        push_json_escaped(&mut writer, self.object_id)?;
+        writer.push_byte(b'"')?;
+        writer.push_byte(b',')?;
+        push_field(&mut writer, "state_topic", self.state_topic)?;
+        for (key, value) in [
+            ("command_topic", self.command_topic),
+            ("availability_topic", self.availability_topic),
+            ("device_class", self.device_class),
+            ("unit_of_measurement", self.unit_of_measurement),
+        ] {
+            if let Some(value) = value {
+                writer.push_byte(b',')?;
+                push_field(&mut writer, key, value)?;
+            }
+        }
+
+        writer.push_str(",\"device\":{\"identifiers\":[\"")?;
+        push_json_escaped(&mut writer, device.identifier)?;
+        writer.push_str("\"],")?;
+        push_field(&mut writer, "name", device.name)?;
+        for (key, value) in [
+            ("manufacturer", device.manufacturer),
+            ("model", device.model),
+            ("sw_version", device.sw_version),
+        ] {
+            if let Some(value) = value {
+                writer.push_byte(b',')?;
+                push_field(&mut writer, key, value)?;
+            }
+        }
+        writer.push_str("}}")?;
+
+        Ok(writer.into_str())
+    }
+}
+
+/// Whether the id is a valid node id or object id, which become levels of the discovery topic.
+fn is_valid_id(id: &str) -> bool {
+    !id.is_empty()
+        && id
+            .bytes()
+            .all(|byte| byte.is_ascii_alphanumeric() || byte == b'_' || byte == b'-')
+}
+
+/// Push `"key":"value"`, escaping the value.
+fn push_field(writer: &mut BufWriter, key: &str, value: &str) -> Result<(), BufferTooSmall> {
+    writer.push_byte(b'"')?;
+    writer.push_str(key)?;
+    writer.push_str("\":\"")?;
+    push_json_escaped(writer, value)?;
+    writer.push_byte(b'"')
+}
+
+/// Push the contents of a JSON string, escaping quotes, backslashes and control characters.
+fn push_json_escaped(writer: &mut BufWriter, s: &str) -> Result<(), BufferTooSmall> {
+    const HEX: &[u8; 16] = b"0123456789abcdef";
+
+    for &byte in s.as_bytes() {
+        match byte {
+            b'"' => writer.push_str("\\\"")?,
+            b'\\' => writer.push_str("\\\\")?,
+            b'\n' => writer.push_str("\\n")?,
+            b'\r' => writer.push_str("\\r")?,
+            b'\t' => writer.push_str("\\t")?,
+            0..0x20 => {
+                writer.push_str("\\u00")?;
+                writer.push_bytes(&[HEX[usize::from(byte >> 4)], HEX[usize::from(byte & 0x0F)]])?;
+            }
+            _ => writer.push_byte(byte)?,
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const DEVICE: Device = Device {
+        identifier: "a1b2c3",
+        name: "Greenhouse",
+        manufacturer: None,
+        model: None,
+        sw_version: None,
+    };
+
+    #[test]
+    fn test_config_topic() {
+        let entity = Entity::binary_sensor("door", "Door", "greenhouse/door");
+        let mut buf = [0u8; 64];
+        assert_eq!(
+            entity.config_topic(DEFAULT_DISCOVERY_PREFIX, "greenhouse", &mut buf),
+            Ok("homeassistant/binary_sensor/greenhouse/door/config")
+        );
+
+        let mut buf = [0u8; 16];
+        assert_eq!(
+            entity.config_topic(DEFAULT_DISCOVERY_PREFIX, "greenhouse", &mut buf),
+            Err(ConfigTopicError::BufferTooSmall)
+        );
+    }
+
+    #[test]
+    fn test_config_topic_invalid_id() {
+        let mut buf = [0u8; 64];
+        let entity = Entity::sensor("temp-1_a", "Temperature", "t");
+        assert!(entity.config_topic("ha", "node-1_b", &mut buf).is_ok());
+
+        for node_id in ["", "a/b", "+", "#", "a b"] {
+            assert_eq!(
+                entity.config_topic("ha", node_id, &mut buf),
+                Err(ConfigTopicError::InvalidId)
+            );
+        }
+        for object_id in ["", "a/b", "+", "#"] {
+            let entity = Entity::sensor(object_id, "Temperature", "t");
+            assert_eq!(
+                entity.config_topic("ha", "node", &mut buf),
+                Err(ConfigTopicError::InvalidId)
+            );
+        }
+    }
+
+    #[test]
+    fn test_sensor_config_payload() {
+        let mut entity = Entity::sensor("temperature", "Temperature", "greenhouse/temperature");
+        entity.device_class = Some("temperature");
+        entity.unit_of_measurement = Some("°C");
+        let device = Device {
+            manufacturer: Some("ACME"),
+            sw_version: Some("1.2.0"),
+            ..DEVICE
+        };
+
+        let mut buf = [0u8; 512];
+        assert_eq!(
+            entity.config_payload(&device, &mut buf),
+            Ok(
+                r#"{"name":"Temperature","unique_id":"a1b2c3_temperature","state_topic":"greenhouse/temperature","device_class":"temperature","unit_of_measurement":"°C","device":{"identifiers":["a1b2c3"],"name":"Greenhouse","manufacturer":"ACME","sw_version":"1.2.0"}}"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_switch_config_payload() {
+        let mut entity = Entity::switch("pump", "Pump", "greenhouse/pump", "greenhouse/pump/set");
+        entity.availability_topic = Some("greenhouse/status");
+
+        let mut buf = [0u8; 512];
+        assert_eq!(
+            entity.config_payload(&DEVICE, &mut buf),
+            Ok(
+                r#"{"name":"Pump","unique_id":"a1b2c3_pump","state_topic":"greenhouse/pump","command_topic":"greenhouse/pump/set","availability_topic":"greenhouse/status","device":{"identifiers":["a1b2c3"],"name":"Greenhouse"}}"#
+            )
+        );
+    }
+
+    #[test]
+    fn test_config_payload_escapes_strings() {
+        let entity = Entity::sensor("x", "Say \"hi\"\\\n\u{1}", "t");
+        let mut buf = [0u8; 256];
+        let payload = entity.config_payload(&DEVICE, &mut buf).unwrap();
+        assert!(payload.starts_with(r#"{"name":"Say \"hi\"\\\n\u0001","#));
+    }
+
+    #[test]
+    fn test_config_payload_buffer_too_small() {
+        let entity = Entity::sensor("x", "X", "t");
+        let mut buf = [0u8; 64];
+        assert_eq!(
+            entity.config_payload(&DEVICE, &mut buf),
+            Err(BufferTooSmall)
+        );
+    }
+}
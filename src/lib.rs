@@ -1,2 +1,22 @@
+#[cfg(feature = "aws-iot")]
+pub mod aws_iot;
+#[cfg(feature = "azure-iot")]
+pub mod azure_iot;
+pub mod batch;
+mod buf_writer;
+pub mod cache;
+pub mod clock;
 pub mod error;
+#[cfg(feature = "home-assistant")]
+pub mod home_assistant;
+pub mod mdns;
+pub mod mqtt_sn;
 pub mod packet;
+pub mod queue;
+pub mod retained;
+#[cfg(feature = "sparkplug")]
+pub mod sparkplug;
+pub mod subscriptions;
+pub mod topic;
+pub mod transport;
+pub mod url;
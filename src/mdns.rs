@@ -0,0 +1,393 @@
+//! This module contains the messages for discovering brokers on the local network through
+//! DNS-SD over multicast DNS, i.e. instances of the `_mqtt._tcp.local` service.
+//!
+//! Sending [`QUERY`] to `224.0.0.251:5353` and passing the received responses to [`endpoints`]
+//! yields the addresses and ports of the brokers that answered. Sending and receiving the
+//! datagrams is left to the application's UDP socket.
+
+use core::convert::Infallible;
+
+use crate::error::Error;
+use crate::packet::data_representation::{take, take_u8, take_u16};
+
+/// The multicast address mDNS queries are sent to.
+pub const MULTICAST_ADDRESS: [u8; 4] = [224, 0, 0, 251];
+pub const PORT: u16 = 5353;
+
+/// Query for the `PTR` records of the `_mqtt._tcp.local` service.
+#[rustfmt::skip]
+pub const QUERY: [u8; 34] = [
+    // Header: id, flags, one question, no records.
+    0, 0, 0, 0, 0, 1, 0, 0, 0, 0, 0, 0,
+    // Name.
+    5, b'_', b'm', b'q', b't', b't',
+    4, b'_', b't', b'c', b'p',
+    5, b'l', b'o', b'c', b'a', b'l',
+    0,
+    // Type PTR, class IN.
+    0, 12, 0, 1,
+];
+
+const SERVICE_LABELS: [&[u8]; 3] = [b"_mqtt", b"_tcp", b"local"];
+
+const TYPE_A: u16 = 1;
+const TYPE_SRV: u16 = 33;
+const FLAG_RESPONSE: u16 = 0x8000;
+const NAME_POINTER_MASK: u8 = 0xC0;
+/// Upper bound on the pointers followed in one name, protecting against pointer loops.
+const MAX_NAME_POINTERS: usize = 16;
+
+/// A broker announced in a response.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Endpoint {
+    pub address: [u8; 4],
+    pub port: u16,
+}
+
+/// Collect the endpoints of the `_mqtt._tcp.local` instances in the response into `out`, returning
+/// the part of `out` used.
+///
+/// An endpoint is found for each `SRV` record of an instance whose target host has an `A` record
+/// in the same response, as responders usually include them as additional records. Endpoints
+/// that don't fit into `out` are skipped.
+pub fn endpoints<'b>(
+    response: &[u8],
+    out: &'b mut [Endpoint],
+) -> Result<&'b [Endpoint], Error<Infallible>> {
+    let mut input = response;
+    let _id = take_u16(&mut input)?;
+    let flags = take_u16(&mut input)?;
+    if flags & FLAG_RESPONSE == 0 {
+        return Err(Error::MalformedPacket);
+    }
+    let question_count = take_u16(&mut input)?;
+    let record_count = [
+        take_u16(&mut input)?,
+        take_u16(&mut input)?,
+        take_u16(&mut input)?,
+    ]
+    .into_iter()
+    .map(usize::from)
+    .sum::<usize>();
+
+    for _ in 0..question_count {
+        skip_name(&mut input)?;
+        take(&mut input, 4)?;
+    }
+    let records_start = response.len() - input.len();
+
+    let mut len = 0;
+    let mut records = Records::new(response, records_start, record_count);
+    while let Some(record) = records.next().transpose()? {
+        if record.record_type != TYPE_SRV || !is_service_instance(response, record.name)? {
+            continue;
+        }
+
+        let mut data = record.data;
+        let _priority = take_u16(&mut data)?;
+        let _weight = take_u16(&mut data)?;
+        let port = take_u16(&mut data)?;
+        let target = record.data_offset + 6;
+
+        let mut hosts = Records::new(response, records_start, record_count);
+        while let Some(host) = hosts.next().transpose()? {
+            if host.record_type != TYPE_A || !names_equal(response, host.name, target)? {
+                continue;
+            }
+            let address = host.data.try_into().map_err(|_| Error::MalformedPacket)?;
+            if let Some(slot) = out.get_mut(len) {
+                *slot = Endpoint { address, port };
+                len += 1;
+            }
+        }
+    }
+
+    Ok(&out[..len])
+}
+
+struct Record<'a> {
+    /// Offset of the owner name in the message.
+    name: usize,
+    record_type: u16,
+    data: &'a [u8],
+    /// Offset of the data in the message, for names within the data.
+    data_offset: usize,
+}
+
+/// Iterator over the resource records of a message, starting at the given offset.
+struct Records<'a> {
+    message: &'a [u8],
+    offset: usize,
+    remaining: usize,
+}
+
+impl<'a> Records<'a> {
+    fn new(message: &'a [u8], offset: usize, count: usize) -> Self {
+        Self {
+            message,
+            offset,
+            remaining: count,
+        }
+    }
+
+    fn take_record(&mut self) -> Result<Record<'a>, Error<Infallible>> {
+        let mut input = self
+            .message
+            .get(self.offset..)
+            .ok_or(Error::MalformedPacket)?;
+        let name = self.offset;
+        skip_name(&mut input)?;
+        let record_type = take_u16(&mut input)?;
+        // Class and TTL.
+        take(&mut input, 6)?;
+        let data_len = take_u16(&mut input)?;
+        let data_offset = self.message.len() - input.len();
+        let data = take(&mut input, usize::from(data_len))?;
+        self.offset = self.message.len() - input.len();
+
+        Ok(Record {
+            name,
+            record_type,
+            data,
+            data_offset,
+        })
+    }
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = Result<Record<'a>, Error<Infallible>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        self.remaining -= 1;
+
+        let result = self.take_record();
+        if result.is_err() {
+            self.remaining = 0;
+        }
+        Some(result)
+    }
+}
+
+/// Skip the name at the start of the input, which ends with an empty label or a pointer.
+fn skip_name(input: &mut &[u8]) -> Result<(), Error<Infallible>> {
+    loop {
+        let len = take_u8(input)?;
+        if len & NAME_POINTER_MASK == NAME_POINTER_MASK {
+            take_u8(input)?;
+            return Ok(());
+        }
+        if len & NAME_POINTER_MASK != 0 {
+            return Err(Error::MalformedPacket);
+        }
+        if len == 0 {
+            return Ok(());
+        }
+        take(input, usize::from(len))?;
+    }
+}
+
+/// Iterator over the labels of the name at the given offset, following compression pointers.
+struct Labels<'a> {
+    message: &'a [u8],
+    offset: usize,
+    pointers: usize,
+    done: bool,
+}
+
+impl<'a> Labels<'a> {
+    fn new(message: &'a [u8], offset: usize) -> Self {
+        Self {
+            message,
+            offset,
+            pointers: 0,
+            done: false,
+        }
+    }
+
+    fn take_label(&mut self) -> Result<Option<&'a [u8]>, Error<Infallible>> {
+        loop {
+            let mut input = self
+                .message
+                .get(self.offset..)
+                .ok_or(Error::MalformedPacket)?;
+            let len = take_u8(&mut input)?;
+            if len & NAME_POINTER_MASK == NAME_POINTER_MASK {
+                self.pointers += 1;
+                if self.pointers > MAX_NAME_POINTERS {
+                    return Err(Error::MalformedPacket);
+                }
+                let low = take_u8(&mut input)?;
+                self.offset = usize::from(u16::from_be_bytes([len & !NAME_POINTER_MASK, low]));
+                continue;
+            }
+            if len & NAME_POINTER_MASK != 0 {
+                return Err(Error::MalformedPacket);
+            }
+            if len == 0 {
+                return Ok(None);
+            }
+
+            let label = take(&mut input, usize::from(len))?;
+            self.offset = self.message.len() - input.len();
+            return Ok(Some(label));
+        }
+    }
+}
+
+impl<'a> Iterator for Labels<'a> {
+    type Item = Result<&'a [u8], Error<Infallible>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.done {
+            return None;
+        }
+
+        let result = self.take_label();
+        match result {
+            Ok(Some(label)) => Some(Ok(label)),
+            Ok(None) => {
+                self.done = true;
+                None
+            }
+            Err(e) => {
+                self.done = true;
+                Some(Err(e))
+            }
+        }
+    }
+}
+
+/// Compare the names at the given offsets, ignoring ASCII case as DNS does.
+fn names_equal(message: &[u8], a: usize, b: usize) -> Result<bool, Error<Infallible>> {
+    let mut a = Labels::new(message, a);
+    let mut b = Labels::new(message, b);
+    loop {
+        match (a.next().transpose()?, b.next().transpose()?) {
+            (None, None) => return Ok(true),
+            (Some(a), Some(b)) if a.eq_ignore_ascii_case(b) => {}
+            _ => return Ok(false),
+        }
+    }
+}
+
+/// Whether the name at the given offset is `{instance}._mqtt._tcp.local`.
+fn is_service_instance(message: &[u8], name: usize) -> Result<bool, Error<Infallible>> {
+    let mut count = 0;
+    let mut labels = Labels::new(message, name);
+    while labels.next().transpose()?.is_some() {
+        count += 1;
+    }
+    if count <= SERVICE_LABELS.len() {
+        return Ok(false);
+    }
+
+    let mut labels = Labels::new(message, name);
+    for _ in 0..count - SERVICE_LABELS.len() {
+        labels.next().transpose()?;
+    }
+    for expected in SERVICE_LABELS {
+        match labels.next().transpose()? {
+            Some(label) if label.eq_ignore_ascii_case(expected) => {}
+            _ => return Ok(false),
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A response as sent by Avahi: the `PTR` answer, followed by the `SRV`, `TXT` and `A`
+    /// records as additional records, with compressed names.
+    #[rustfmt::skip]
+    const RESPONSE: &[u8] = &[
+        // Header: response, no questions, one answer, three additional records.
+        0x00, 0x00, 0x84, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00, 0x03,
+        // 12: PTR _mqtt._tcp.local -> broker._mqtt._tcp.local
+        5, b'_', b'm', b'q', b't', b't', 4, b'_', b't', b'c', b'p', 5, b'l', b'o', b'c', b'a', b'l', 0,
+        0x00, 0x0C, 0x00, 0x01, 0x00, 0x00, 0x11, 0x94, 0x00, 0x09,
+        // 40: broker + pointer to 12.
+        6, b'b', b'r', b'o', b'k', b'e', b'r', 0xC0, 12,
+        // 49: SRV broker._mqtt._tcp.local -> pi.local:1883
+        0xC0, 40,
+        0x00, 0x21, 0x80, 0x01, 0x00, 0x00, 0x00, 0x78, 0x00, 0x0B,
+        0x00, 0x00, 0x00, 0x00, 0x07, 0x5B,
+        // 67: pi + pointer to local at 23.
+        2, b'p', b'i', 0xC0, 23,
+        // TXT broker._mqtt._tcp.local
+        0xC0, 40,
+        0x00, 0x10, 0x80, 0x01, 0x00, 0x00, 0x11, 0x94, 0x00, 0x01, 0x00,
+        // A pi.local
+        0xC0, 67,
+        0x00, 0x01, 0x80, 0x01, 0x00, 0x00, 0x00, 0x78, 0x00, 0x04, 192, 168, 1, 20,
+    ];
+
+    #[test]
+    fn test_query() {
+        assert_eq!(
+            QUERY[12..],
+            *b"\x05_mqtt\x04_tcp\x05local\x00\x00\x0C\x00\x01"
+        );
+    }
+
+    #[test]
+    fn test_endpoints() {
+        let mut out = [Endpoint::default(); 4];
+        assert_eq!(
+            endpoints(RESPONSE, &mut out).unwrap(),
+            [Endpoint {
+                address: [192, 168, 1, 20],
+                port: 1883,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_endpoints_out_full() {
+        let mut out = [];
+        assert_eq!(endpoints(RESPONSE, &mut out).unwrap(), []);
+    }
+
+    #[test]
+    fn test_endpoints_ignores_other_services() {
+        let mut response = RESPONSE.to_vec();
+        // Rename the service in the shared name to _http.
+        response[13..18].copy_from_slice(b"_http");
+        let mut out = [Endpoint::default(); 4];
+        assert_eq!(endpoints(&response, &mut out).unwrap(), []);
+    }
+
+    #[test]
+    fn test_endpoints_rejects_queries() {
+        let mut out = [Endpoint::default(); 4];
+        assert!(matches!(
+            endpoints(&QUERY, &mut out),
+            Err(Error::MalformedPacket)
+        ));
+    }
+
+    #[test]
+    fn test_endpoints_rejects_truncated_response() {
+        let mut out = [Endpoint::default(); 4];
+        assert!(matches!(
+            endpoints(&RESPONSE[..RESPONSE.len() - 1], &mut out),
+            Err(Error::MalformedPacket)
+        ));
+    }
+
+    #[test]
+    fn test_endpoints_rejects_pointer_loop() {
+        let mut response = RESPONSE.to_vec();
+        // Point the name of the SRV record at itself.
+        response[50] = 49;
+        let mut out = [Endpoint::default(); 4];
+        assert!(matches!(
+            endpoints(&response, &mut out),
+            Err(Error::MalformedPacket)
+        ));
+    }
+}
@@ -0,0 +1,953 @@
+//! This module contains the packet formats of MQTT-SN v1.2, the MQTT variant for sensor networks.
+//!
+//! MQTT-SN is carried over datagram transports, so packets are decoded from a complete datagram
+//! held in memory and borrow their variable-length fields from it.
+
+use crate::{
+    buf_writer::{BufWriter, BufferTooSmall},
+    error::Error,
+    packet::data_representation::{take_u8, take_u16},
+};
+use core::convert::Infallible;
+
+/// Protocol ID carried in the CONNECT packet.
+const PROTOCOL_ID: u8 = 0x01;
+
+/// First octet of the length field if the length is encoded in the two following octets.
+const THREE_OCTET_LENGTH_MARKER: u8 = 0x01;
+
+const DUP_BIT: u8 = 0b1000_0000;
+const QOS_SHIFT: u8 = 5;
+const QOS_MASK: u8 = 0b0110_0000;
+const RETAIN_BIT: u8 = 0b0001_0000;
+const WILL_BIT: u8 = 0b0000_1000;
+const CLEAN_SESSION_BIT: u8 = 0b0000_0100;
+const TOPIC_ID_TYPE_MASK: u8 = 0b0000_0011;
+
+const TOPIC_ID_TYPE_NORMAL: u8 = 0b00;
+const TOPIC_ID_TYPE_PREDEFINED: u8 = 0b01;
+const TOPIC_ID_TYPE_SHORT_NAME: u8 = 0b10;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QoS {
+    AtMostOnce,
+    AtLeastOnce,
+    ExactlyOnce,
+    /// QoS level -1, publishing to a predefined topic or short topic name without a connection.
+    WithoutConnection,
+}
+
+impl QoS {
+    /// Convert to the raw 2-bit unsigned value that represents the given QoS level.
+    pub fn to_bits(&self) -> u8 {
+        match self {
+            QoS::AtMostOnce => 0b00,
+            QoS::AtLeastOnce => 0b01,
+            QoS::ExactlyOnce => 0b10,
+            QoS::WithoutConnection => 0b11,
+        }
+    }
+
+    /// Get the [`QoS`] that the given bits represent.
+    ///
+    /// Bits other than the lowest two are discarded.
+    pub fn from_bits(bits: u8) -> Self {
+        match bits & 0b11 {
+            0b00 => QoS::AtMostOnce,
+            0b01 => QoS::AtLeastOnce,
+            0b10 => QoS::ExactlyOnce,
+            0b11 => QoS::WithoutConnection,
+            _ => unreachable!("Upper bits should be zero"),
+        }
+    }
+}
+
+/// The flags octet, excluding the topic id type, which is part of [`TopicId`] and
+/// [`SubscribeTopic`].
+///
+/// The will flag is not supported, as the WILLTOPICREQ, WILLTOPIC and WILLMSG exchange it starts
+/// isn't. Packets with the flag set fail to decode.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Flags {
+    pub dup: bool,
+    pub qos: QoS,
+    pub retain: bool,
+    pub clean_session: bool,
+}
+
+impl Flags {
+    fn to_byte(self, topic_id_type: u8) -> u8 {
+        let mut byte = (self.qos.to_bits() << QOS_SHIFT) | (topic_id_type & TOPIC_ID_TYPE_MASK);
+        if self.dup {
+            byte |= DUP_BIT;
+        }
+        if self.retain {
+            byte |= RETAIN_BIT;
+        }
+        if self.clean_session {
+            byte |= CLEAN_SESSION_BIT;
+        }
+        byte
+    }
+
+    fn from_byte(byte: u8) -> Result<Self, Error<Infallible>> {
+        if byte & WILL_BIT != 0 {
+            return Err(Error::MalformedPacket);
+        }
+
+        Ok(Self {
+            dup: byte & DUP_BIT != 0,
+            qos: QoS::from_bits((byte & QOS_MASK) >> QOS_SHIFT),
+            retain: byte & RETAIN_BIT != 0,
+            clean_session: byte & CLEAN_SESSION_BIT != 0,
+        })
+    }
+}
+
+/// The two-octet topic field of PUBLISH packets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TopicId {
+    /// A topic id assigned through REGISTER.
+    Normal(u16),
+    /// A topic id agreed on in advance by client and gateway.
+    Predefined(u16),
+    /// A topic name of exactly two characters.
+    ShortName([u8; 2]),
+}
+
+impl TopicId {
+    fn type_bits(&self) -> u8 {
+        match self {
+            TopicId::Normal(_) => TOPIC_ID_TYPE_NORMAL,
+            TopicId::Predefined(_) => TOPIC_ID_TYPE_PREDEFINED,
+            TopicId::ShortName(_) => TOPIC_ID_TYPE_SHORT_NAME,
+        }
+    }
+
+    fn to_u16(self) -> u16 {
+        match self {
+            TopicId::Normal(id) | TopicId::Predefined(id) => id,
+            TopicId::ShortName(name) => u16::from_be_bytes(name),
+        }
+    }
+
+    fn from_parts(type_bits: u8, value: u16) -> Result<Self, Error<Infallible>> {
+        match type_bits {
+            TOPIC_ID_TYPE_NORMAL => Ok(TopicId::Normal(value)),
+            TOPIC_ID_TYPE_PREDEFINED => Ok(TopicId::Predefined(value)),
+            TOPIC_ID_TYPE_SHORT_NAME => Ok(TopicId::ShortName(value.to_be_bytes())),
+            _ => Err(Error::MalformedPacket),
+        }
+    }
+}
+
+/// The topic field of SUBSCRIBE packets.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SubscribeTopic<'a> {
+    /// A full topic name or topic filter.
+    Name(&'a str),
+    /// A topic id agreed on in advance by client and gateway.
+    Predefined(u16),
+    /// A topic name of exactly two characters.
+    ShortName([u8; 2]),
+}
+
+impl SubscribeTopic<'_> {
+    fn type_bits(&self) -> u8 {
+        match self {
+            SubscribeTopic::Name(_) => TOPIC_ID_TYPE_NORMAL,
+            SubscribeTopic::Predefined(_) => TOPIC_ID_TYPE_PREDEFINED,
+            SubscribeTopic::ShortName(_) => TOPIC_ID_TYPE_SHORT_NAME,
+        }
+    }
+
+    fn encoded_len(&self) -> usize {
+        match self {
+            SubscribeTopic::Name(name) => name.len(),
+            SubscribeTopic::Predefined(_) | SubscribeTopic::ShortName(_) => 2,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReturnCode {
+    Accepted,
+    RejectedCongestion,
+    RejectedInvalidTopicId,
+    RejectedNotSupported,
+}
+
+impl ReturnCode {
+    pub fn to_byte(&self) -> u8 {
+        match self {
+            ReturnCode::Accepted => 0x00,
+            ReturnCode::RejectedCongestion => 0x01,
+            ReturnCode::RejectedInvalidTopicId => 0x02,
+            ReturnCode::RejectedNotSupported => 0x03,
+        }
+    }
+
+    /// Get the [`ReturnCode`] that the given byte represents.
+    ///
+    /// Returns `None` for reserved values.
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0x00 => Some(ReturnCode::Accepted),
+            0x01 => Some(ReturnCode::RejectedCongestion),
+            0x02 => Some(ReturnCode::RejectedInvalidTopicId),
+            0x03 => Some(ReturnCode::RejectedNotSupported),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MsgType {
+    Connect,
+    ConnAck,
+    Register,
+    RegAck,
+    Publish,
+    PubAck,
+    Subscribe,
+    SubAck,
+    PingReq,
+    PingResp,
+    Disconnect,
+}
+
+impl MsgType {
+    pub fn to_byte(&self) -> u8 {
+        match self {
+            MsgType::Connect => 0x04,
+            MsgType::ConnAck => 0x05,
+            MsgType::Register => 0x0A,
+            MsgType::RegAck => 0x0B,
+            MsgType::Publish => 0x0C,
+            MsgType::PubAck => 0x0D,
+            MsgType::Subscribe => 0x12,
+            MsgType::SubAck => 0x13,
+            MsgType::PingReq => 0x16,
+            MsgType::PingResp => 0x17,
+            MsgType::Disconnect => 0x18,
+        }
+    }
+
+    /// Get the [`MsgType`] that the given byte represents.
+    ///
+    /// Returns `None` for reserved values and for message types that are not supported yet.
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        match byte {
+            0x04 => Some(MsgType::Connect),
+            0x05 => Some(MsgType::ConnAck),
+            0x0A => Some(MsgType::Register),
+            0x0B => Some(MsgType::RegAck),
+            0x0C => Some(MsgType::Publish),
+            0x0D => Some(MsgType::PubAck),
+            0x12 => Some(MsgType::Subscribe),
+            0x13 => Some(MsgType::SubAck),
+            0x16 => Some(MsgType::PingReq),
+            0x17 => Some(MsgType::PingResp),
+            0x18 => Some(MsgType::Disconnect),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum EncodeError {
+    BufferTooSmall,
+    /// The packet is longer than the three octet length field can represent.
+    PacketTooLong,
+}
+
+impl From<BufferTooSmall> for EncodeError {
+    fn from(_: BufferTooSmall) -> Self {
+        EncodeError::BufferTooSmall
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub enum Packet<'a> {
+    Connect {
+        flags: Flags,
+        /// Keep alive duration in seconds.
+        duration: u16,
+        client_id: &'a str,
+    },
+    ConnAck {
+        return_code: ReturnCode,
+    },
+    Register {
+        topic_id: u16,
+        msg_id: u16,
+        topic_name: &'a str,
+    },
+    RegAck {
+        topic_id: u16,
+        msg_id: u16,
+        return_code: ReturnCode,
+    },
+    Publish {
+        flags: Flags,
+        topic_id: TopicId,
+        msg_id: u16,
+        data: &'a [u8],
+    },
+    PubAck {
+        topic_id: u16,
+        msg_id: u16,
+        return_code: ReturnCode,
+    },
+    Subscribe {
+        flags: Flags,
+        msg_id: u16,
+        topic: SubscribeTopic<'a>,
+    },
+    SubAck {
+        flags: Flags,
+        topic_id: u16,
+        msg_id: u16,
+        return_code: ReturnCode,
+    },
+    PingReq {
+        /// Set by sleeping clients to retrieve buffered messages.
+        client_id: Option<&'a str>,
+    },
+    PingResp,
+    Disconnect {
+        /// Sleep duration in seconds, set by clients that are going to sleep.
+        duration: Option<u16>,
+    },
+}
+
+impl<'a> Packet<'a> {
+    pub fn msg_type(&self) -> MsgType {
+        match self {
+            Packet::Connect { .. } => MsgType::Connect,
+            Packet::ConnAck { .. } => MsgType::ConnAck,
+            Packet::Register { .. } => MsgType::Register,
+            Packet::RegAck { .. } => MsgType::RegAck,
+            Packet::Publish { .. } => MsgType::Publish,
+            Packet::PubAck { .. } => MsgType::PubAck,
+            Packet::Subscribe { .. } => MsgType::Subscribe,
+            Packet::SubAck { .. } => MsgType::SubAck,
+            Packet::PingReq { .. } => MsgType::PingReq,
+            Packet::PingResp => MsgType::PingResp,
+            Packet::Disconnect { .. } => MsgType::Disconnect,
+        }
+    }
+
+    /// Decode a packet from a datagram, which must contain exactly one packet.
+    pub fn decode(datagram: &'a [u8]) -> Result<Self, Error<Infallible>> {
+        let mut input = datagram;
+
+        let length = match take_u8(&mut input)? {
+            THREE_OCTET_LENGTH_MARKER => usize::from(take_u16(&mut input)?),
+            length => usize::from(length),
+        };
+        if length != datagram.len() {
+            return Err(Error::MalformedPacket);
+        }
+
+        let msg_type = MsgType::from_byte(take_u8(&mut input)?).ok_or(Error::MalformedPacket)?;
+        let packet = match msg_type {
+            MsgType::Connect => {
+                let flags = take_u8(&mut input)?;
+                if take_u8(&mut input)? != PROTOCOL_ID {
+                    return Err(Error::MalformedPacket);
+                }
+                Packet::Connect {
+                    flags: Flags::from_byte(flags)?,
+                    duration: take_u16(&mut input)?,
+                    client_id: take_str(&mut input)?,
+                }
+            }
+            MsgType::ConnAck => Packet::ConnAck {
+                return_code: take_return_code(&mut input)?,
+            },
+            MsgType::Register => Packet::Register {
+                topic_id: take_u16(&mut input)?,
+                msg_id: take_u16(&mut input)?,
+                topic_name: take_str(&mut input)?,
+            },
+            MsgType::RegAck => Packet::RegAck {
+                topic_id: take_u16(&mut input)?,
+                msg_id: take_u16(&mut input)?,
+                return_code: take_return_code(&mut input)?,
+            },
+            MsgType::Publish => {
+                let flags = take_u8(&mut input)?;
+                let topic_id = take_u16(&mut input)?;
+                Packet::Publish {
+                    flags: Flags::from_byte(flags)?,
+                    topic_id: TopicId::from_parts(flags & TOPIC_ID_TYPE_MASK, topic_id)?,
+                    msg_id: take_u16(&mut input)?,
+                    data: core::mem::take(&mut input),
+                }
+            }
+            MsgType::PubAck => Packet::PubAck {
+                topic_id: take_u16(&mut input)?,
+                msg_id: take_u16(&mut input)?,
+                return_code: take_return_code(&mut input)?,
+            },
+            MsgType::Subscribe => {
+                let flags = take_u8(&mut input)?;
+                let msg_id = take_u16(&mut input)?;
+                let topic = match flags & TOPIC_ID_TYPE_MASK {
+                    TOPIC_ID_TYPE_NORMAL => SubscribeTopic::Name(take_str(&mut input)?),
+                    TOPIC_ID_TYPE_PREDEFINED => SubscribeTopic::Predefined(take_u16(&mut input)?),
+                    TOPIC_ID_TYPE_SHORT_NAME => {
+                        SubscribeTopic::ShortName(take_u16(&mut input)?.to_be_bytes())
+                    }
+                    _ => return Err(Error::MalformedPacket),
+                };
+                Packet::Subscribe {
+                    flags: Flags::from_byte(flags)?,
+                    msg_id,
+                    topic,
+                }
+            }
+            MsgType::SubAck => Packet::SubAck {
+                flags: Flags::from_byte(take_u8(&mut input)?)?,
+                topic_id: take_u16(&mut input)?,
+                msg_id: take_u16(&mut input)?,
+                return_code: take_return_code(&mut input)?,
+            },
+            MsgType::PingReq => Packet::PingReq {
+                client_id: match input.is_empty() {
+                    true => None,
+                    false => Some(take_str(&mut input)?),
+                },
+            },
+            MsgType::PingResp => Packet::PingResp,
+            MsgType::Disconnect => Packet::Disconnect {
+                duration: match input.is_empty() {
+                    true => None,
+                    false => Some(take_u16(&mut input)?),
+                },
+            },
+        };
+
+        if !input.is_empty() {
+            // Trailing bytes that are not part of any field.
+            return Err(Error::MalformedPacket);
+        }
+
+        Ok(packet)
+    }
+
+    /// Number of bytes the encoded packet takes up, including the length field.
+    pub fn encoded_len(&self) -> usize {
+        // Single octet length field and message type octet.
+        let len = 2 + self.fields_len();
+        if len <= usize::from(u8::MAX) {
+            len
+        } else {
+            // The three octet length field takes up two more octets.
+            len + 2
+        }
+    }
+
+    fn fields_len(&self) -> usize {
+        match self {
+            Packet::Connect { client_id, .. } => 4 + client_id.len(),
+            Packet::ConnAck { .. } => 1,
+            Packet::Register { topic_name, .. } => 4 + topic_name.len(),
+            Packet::RegAck { .. } | Packet::PubAck { .. } => 5,
+            Packet::Publish { data, .. } => 5 + data.len(),
+            Packet::Subscribe { topic, .. } => 3 + topic.encoded_len(),
+            Packet::SubAck { .. } => 6,
+            Packet::PingReq { client_id } => client_id.map_or(0, str::len),
+            Packet::PingResp => 0,
+            Packet::Disconnect { duration } => duration.map_or(0, |_| 2),
+        }
+    }
+
+    /// Encode into the buffer, returning the part of the buffer holding the packet.
+    pub fn encode<'b>(&self, buf: &'b mut [u8]) -> Result<&'b [u8], EncodeError> {
+        let length = self.encoded_len();
+        let length: u16 = length.try_into().map_err(|_| EncodeError::PacketTooLong)?;
+        if buf.len() < usize::from(length) {
+            return Err(EncodeError::BufferTooSmall);
+        }
+
+        let mut writer = BufWriter::new(buf);
+        if length <= u16::from(u8::MAX) {
+            writer.push_byte(length as u8)?;
+        } else {
+            writer.push_byte(THREE_OCTET_LENGTH_MARKER)?;
+            writer.push_bytes(&length.to_be_bytes())?;
+        }
+        writer.push_byte(self.msg_type().to_byte())?;
+
+        match self {
+            Packet::Connect {
+                flags,
+                duration,
+                client_id,
+            } => {
+                writer.push_byte(flags.to_byte(0))?;
+                writer.push_byte(PROTOCOL_ID)?;
+                writer.push_bytes(&duration.to_be_bytes())?;
+                writer.push_str(client_id)?;
+            }
+            Packet::ConnAck { return_code } => writer.push_byte(return_code.to_byte())?,
+            Packet::Register {
+                topic_id,
+                msg_id,
+                topic_name,
+            } => {
+                writer.push_bytes(&topic_id.to_be_bytes())?;
+                writer.push_bytes(&msg_id.to_be_bytes())?;
+                writer.push_str(topic_name)?;
+            }
+            Packet::RegAck {
+                topic_id,
+                msg_id,
+                return_code,
+            }
+            | Packet::PubAck {
+                topic_id,
+                msg_id,
+                return_code,
+            } => {
+                writer.push_bytes(&topic_id.to_be_bytes())?;
+                writer.push_bytes(&msg_id.to_be_bytes())?;
+                writer.push_byte(return_code.to_byte())?;
+            }
+            Packet::Publish {
+                flags,
+                topic_id,
+                msg_id,
+                data,
+            } => {
+                writer.push_byte(flags.to_byte(topic_id.type_bits()))?;
+                writer.push_bytes(&topic_id.to_u16().to_be_bytes())?;
+                writer.push_bytes(&msg_id.to_be_bytes())?;
+                writer.push_bytes(data)?;
+            }
+            Packet::Subscribe {
+                flags,
+                msg_id,
+                topic,
+            } => {
+                writer.push_byte(flags.to_byte(topic.type_bits()))?;
+                writer.push_bytes(&msg_id.to_be_bytes())?;
+                match topic {
+                    SubscribeTopic::Name(name) => writer.push_str(name)?,
+                    SubscribeTopic::Predefined(id) => writer.push_bytes(&id.to_be_bytes())?,
+                    SubscribeTopic::ShortName(name) => writer.push_bytes(name)?,
+                }
+            }
+            Packet::SubAck {
+                flags,
+                topic_id,
+                msg_id,
+                return_code,
+            } => {
+                writer.push_byte(flags.to_byte(0))?;
+                writer.push_bytes(&topic_id.to_be_bytes())?;
+                writer.push_bytes(&msg_id.to_be_bytes())?;
+                writer.push_byte(return_code.to_byte())?;
+            }
+            Packet::PingReq { client_id } => {
+                if let Some(client_id) = client_id {
+                    writer.push_str(client_id)?;
+                }
+            }
+            Packet::PingResp => {}
+            Packet::Disconnect { duration } => {
+                if let Some(duration) = duration {
+                    writer.push_bytes(&duration.to_be_bytes())?;
+                }
+            }
+        }
+
+        Ok(writer.into_bytes())
+    }
+}
+
+/// Take the remainder of the input as a string. Unlike in MQTT, strings in MQTT-SN are not
+/// length-prefixed but extend to the end of the packet.
+fn take_str<'a>(input: &mut &'a [u8]) -> Result<&'a str, Error<Infallible>> {
+    core::str::from_utf8(core::mem::take(input)).map_err(|_| Error::MalformedPacket)
+}
+
+fn take_return_code(input: &mut &[u8]) -> Result<ReturnCode, Error<Infallible>> {
+    ReturnCode::from_byte(take_u8(input)?).ok_or(Error::MalformedPacket)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FLAGS: Flags = Flags {
+        dup: false,
+        qos: QoS::AtMostOnce,
+        retain: false,
+        clean_session: false,
+    };
+
+    #[test]
+    fn test_qos_bits() {
+        for qos in [
+            QoS::AtMostOnce,
+            QoS::AtLeastOnce,
+            QoS::ExactlyOnce,
+            QoS::WithoutConnection,
+        ] {
+            assert_eq!(QoS::from_bits(qos.to_bits()), qos);
+        }
+        assert_eq!(QoS::WithoutConnection.to_bits(), 0b11);
+    }
+
+    #[test]
+    fn test_flags_to_byte() {
+        let flags = Flags {
+            dup: true,
+            qos: QoS::AtLeastOnce,
+            retain: true,
+            clean_session: true,
+        };
+        assert_eq!(flags.to_byte(TOPIC_ID_TYPE_SHORT_NAME), 0b1011_0110);
+        assert_eq!(Flags::from_byte(0b1011_0110).unwrap(), flags);
+        assert!(matches!(
+            Flags::from_byte(WILL_BIT),
+            Err(Error::MalformedPacket)
+        ));
+    }
+
+    #[test]
+    fn test_connect_encode() {
+        let packet = Packet::Connect {
+            flags: Flags {
+                clean_session: true,
+                ..FLAGS
+            },
+            duration: 60,
+            client_id: "dev",
+        };
+        let mut buffer = [0u8; 16];
+        let encoded = packet.encode(&mut buffer).unwrap();
+        assert_eq!(
+            encoded,
+            [0x09, 0x04, 0x04, 0x01, 0x00, 0x3C, b'd', b'e', b'v']
+        );
+        assert_eq!(Packet::decode(encoded).unwrap(), packet);
+    }
+
+    #[test]
+    fn test_connect_decode_wrong_protocol_id() {
+        let data = [0x06, 0x04, 0x04, 0x02, 0x00, 0x3C];
+        assert!(matches!(Packet::decode(&data), Err(Error::MalformedPacket)));
+    }
+
+    #[test]
+    fn test_connack_decode() {
+        assert_eq!(
+            Packet::decode(&[0x03, 0x05, 0x00]).unwrap(),
+            Packet::ConnAck {
+                return_code: ReturnCode::Accepted
+            }
+        );
+        assert_eq!(
+            Packet::decode(&[0x03, 0x05, 0x01]).unwrap(),
+            Packet::ConnAck {
+                return_code: ReturnCode::RejectedCongestion
+            }
+        );
+        assert!(matches!(
+            Packet::decode(&[0x03, 0x05, 0x04]),
+            Err(Error::MalformedPacket)
+        ));
+    }
+
+    #[test]
+    fn test_register_roundtrip() {
+        let packet = Packet::Register {
+            topic_id: 0,
+            msg_id: 0x1234,
+            topic_name: "sensors/temp",
+        };
+        let mut buffer = [0u8; 32];
+        let encoded = packet.encode(&mut buffer).unwrap();
+        assert_eq!(&encoded[..6], [18, 0x0A, 0x00, 0x00, 0x12, 0x34]);
+        assert_eq!(Packet::decode(encoded).unwrap(), packet);
+    }
+
+    #[test]
+    fn test_regack_roundtrip() {
+        let packet = Packet::RegAck {
+            topic_id: 7,
+            msg_id: 0x1234,
+            return_code: ReturnCode::Accepted,
+        };
+        let mut buffer = [0u8; 8];
+        let encoded = packet.encode(&mut buffer).unwrap();
+        assert_eq!(encoded, [0x07, 0x0B, 0x00, 0x07, 0x12, 0x34, 0x00]);
+        assert_eq!(Packet::decode(encoded).unwrap(), packet);
+    }
+
+    #[test]
+    fn test_publish_roundtrip() {
+        let packet = Packet::Publish {
+            flags: Flags {
+                qos: QoS::AtLeastOnce,
+                ..FLAGS
+            },
+            topic_id: TopicId::Normal(7),
+            msg_id: 1,
+            data: b"21.5",
+        };
+        let mut buffer = [0u8; 16];
+        let encoded = packet.encode(&mut buffer).unwrap();
+        assert_eq!(
+            encoded,
+            [
+                0x0B, 0x0C, 0x20, 0x00, 0x07, 0x00, 0x01, b'2', b'1', b'.', b'5'
+            ]
+        );
+        assert_eq!(Packet::decode(encoded).unwrap(), packet);
+    }
+
+    #[test]
+    fn test_publish_topic_id_types() {
+        for topic_id in [
+            TopicId::Normal(1),
+            TopicId::Predefined(2),
+            TopicId::ShortName(*b"ab"),
+        ] {
+            let packet = Packet::Publish {
+                flags: Flags {
+                    qos: QoS::WithoutConnection,
+                    ..FLAGS
+                },
+                topic_id,
+                msg_id: 0,
+                data: &[],
+            };
+            let mut buffer = [0u8; 8];
+            let encoded = packet.encode(&mut buffer).unwrap();
+            assert_eq!(Packet::decode(encoded).unwrap(), packet);
+        }
+    }
+
+    #[test]
+    fn test_publish_decode_reserved_topic_id_type() {
+        let data = [0x07, 0x0C, 0b0000_0011, 0x00, 0x01, 0x00, 0x00];
+        assert!(matches!(Packet::decode(&data), Err(Error::MalformedPacket)));
+    }
+
+    #[test]
+    fn test_publish_three_octet_length() {
+        let data = [0xAA; 300];
+        let packet = Packet::Publish {
+            flags: FLAGS,
+            topic_id: TopicId::Normal(1),
+            msg_id: 0,
+            data: &data,
+        };
+        assert_eq!(packet.encoded_len(), 309);
+
+        let mut buffer = [0u8; 309];
+        let encoded = packet.encode(&mut buffer).unwrap();
+        assert_eq!(&encoded[..4], [0x01, 0x01, 0x35, 0x0C]);
+        assert_eq!(Packet::decode(encoded).unwrap(), packet);
+    }
+
+    #[test]
+    fn test_length_field_boundary() {
+        // 255 bytes in total still fit the single octet length field.
+        let data = [0u8; 248];
+        let packet = Packet::Publish {
+            flags: FLAGS,
+            topic_id: TopicId::Normal(1),
+            msg_id: 0,
+            data: &data,
+        };
+        assert_eq!(packet.encoded_len(), 255);
+
+        let mut buffer = [0u8; 255];
+        let encoded = packet.encode(&mut buffer).unwrap();
+        assert_eq!(encoded[0], 0xFF);
+        assert_eq!(Packet::decode(encoded).unwrap(), packet);
+
+        // One more byte requires the three octet length field.
+        let data = [0u8; 249];
+        let packet = Packet::Publish {
+            flags: FLAGS,
+            topic_id: TopicId::Normal(1),
+            msg_id: 0,
+            data: &data,
+        };
+        assert_eq!(packet.encoded_len(), 258);
+    }
+
+    #[test]
+    fn test_puback_roundtrip() {
+        let packet = Packet::PubAck {
+            topic_id: 7,
+            msg_id: 1,
+            return_code: ReturnCode::RejectedInvalidTopicId,
+        };
+        let mut buffer = [0u8; 8];
+        let encoded = packet.encode(&mut buffer).unwrap();
+        assert_eq!(encoded, [0x07, 0x0D, 0x00, 0x07, 0x00, 0x01, 0x02]);
+        assert_eq!(Packet::decode(encoded).unwrap(), packet);
+    }
+
+    #[test]
+    fn test_subscribe_roundtrip() {
+        for topic in [
+            SubscribeTopic::Name("sensors/+"),
+            SubscribeTopic::Predefined(3),
+            SubscribeTopic::ShortName(*b"ab"),
+        ] {
+            let packet = Packet::Subscribe {
+                flags: Flags {
+                    qos: QoS::ExactlyOnce,
+                    ..FLAGS
+                },
+                msg_id: 5,
+                topic,
+            };
+            let mut buffer = [0u8; 16];
+            let encoded = packet.encode(&mut buffer).unwrap();
+            assert_eq!(Packet::decode(encoded).unwrap(), packet);
+        }
+    }
+
+    #[test]
+    fn test_suback_roundtrip() {
+        let packet = Packet::SubAck {
+            flags: Flags {
+                qos: QoS::AtLeastOnce,
+                ..FLAGS
+            },
+            topic_id: 9,
+            msg_id: 5,
+            return_code: ReturnCode::Accepted,
+        };
+        let mut buffer = [0u8; 8];
+        let encoded = packet.encode(&mut buffer).unwrap();
+        assert_eq!(encoded, [0x08, 0x13, 0x20, 0x00, 0x09, 0x00, 0x05, 0x00]);
+        assert_eq!(Packet::decode(encoded).unwrap(), packet);
+    }
+
+    #[test]
+    fn test_ping_and_disconnect_optional_fields() {
+        let packets = [
+            Packet::PingReq { client_id: None },
+            Packet::PingReq {
+                client_id: Some("dev"),
+            },
+            Packet::PingResp,
+            Packet::Disconnect { duration: None },
+            Packet::Disconnect {
+                duration: Some(3600),
+            },
+        ];
+        for packet in packets {
+            let mut buffer = [0u8; 8];
+            let encoded = packet.encode(&mut buffer).unwrap();
+            assert_eq!(Packet::decode(encoded).unwrap(), packet);
+        }
+
+        assert_eq!(
+            Packet::decode(&[0x02, 0x16]).unwrap(),
+            Packet::PingReq { client_id: None }
+        );
+        assert_eq!(Packet::decode(&[0x02, 0x17]).unwrap(), Packet::PingResp);
+    }
+
+    #[test]
+    fn test_decode_encode_roundtrip() {
+        let datagrams: [&[u8]; 14] = [
+            &[0x09, 0x04, 0x04, 0x01, 0x00, 0x3C, b'd', b'e', b'v'],
+            &[0x03, 0x05, 0x03],
+            &[0x07, 0x0A, 0x00, 0x01, 0x00, 0x02, b'a'],
+            &[0x07, 0x0B, 0x00, 0x01, 0x00, 0x02, 0x00],
+            &[0x08, 0x0C, 0xB1, 0x00, 0x01, 0x00, 0x02, 0xFF],
+            &[0x07, 0x0C, 0x62, b'a', b'b', 0x00, 0x00],
+            &[0x07, 0x0D, 0x00, 0x01, 0x00, 0x02, 0x01],
+            &[0x07, 0x12, 0x20, 0x00, 0x05, b'a', b'/'],
+            &[0x07, 0x12, 0x41, 0x00, 0x05, 0x00, 0x03],
+            &[0x08, 0x13, 0x40, 0x00, 0x09, 0x00, 0x05, 0x02],
+            &[0x02, 0x16],
+            &[0x03, 0x16, b'd'],
+            &[0x02, 0x17],
+            &[0x04, 0x18, 0x0E, 0x10],
+        ];
+        for datagram in datagrams {
+            let packet = Packet::decode(datagram).unwrap();
+            let mut buffer = [0u8; 16];
+            let encoded = packet.encode(&mut buffer).unwrap();
+            assert_eq!(encoded, datagram);
+            assert_eq!(Packet::decode(encoded).unwrap(), packet);
+        }
+    }
+
+    #[test]
+    fn test_decode_length_mismatch() {
+        // Length field claims more bytes than the datagram holds.
+        assert!(matches!(
+            Packet::decode(&[0x04, 0x05, 0x00]),
+            Err(Error::MalformedPacket)
+        ));
+        // Datagram holds more bytes than the length field claims.
+        assert!(matches!(
+            Packet::decode(&[0x03, 0x05, 0x00, 0x00]),
+            Err(Error::MalformedPacket)
+        ));
+    }
+
+    #[test]
+    fn test_decode_trailing_bytes() {
+        assert!(matches!(
+            Packet::decode(&[0x04, 0x05, 0x00, 0x00]),
+            Err(Error::MalformedPacket)
+        ));
+    }
+
+    #[test]
+    fn test_decode_unknown_msg_type() {
+        assert!(matches!(
+            Packet::decode(&[0x02, 0xFF]),
+            Err(Error::MalformedPacket)
+        ));
+    }
+
+    #[test]
+    fn test_decode_empty() {
+        assert!(matches!(Packet::decode(&[]), Err(Error::MalformedPacket)));
+    }
+
+    #[test]
+    fn test_encode_buffer_too_small() {
+        let packet = Packet::ConnAck {
+            return_code: ReturnCode::Accepted,
+        };
+        let mut buffer = [0u8; 2];
+        assert_eq!(packet.encode(&mut buffer), Err(EncodeError::BufferTooSmall));
+    }
+
+    #[test]
+    fn test_encode_packet_too_long() {
+        let data = [0u8; 65_535];
+        let packet = Packet::Publish {
+            flags: FLAGS,
+            topic_id: TopicId::Normal(1),
+            msg_id: 0,
+            data: &data,
+        };
+        let mut buffer = [0u8; 8];
+        assert_eq!(packet.encode(&mut buffer), Err(EncodeError::PacketTooLong));
+    }
+
+    #[test]
+    fn test_decode_will_flag() {
+        let data = [0x06, 0x04, 0x0C, 0x01, 0x00, 0x3C];
+        assert!(matches!(Packet::decode(&data), Err(Error::MalformedPacket)));
+    }
+}
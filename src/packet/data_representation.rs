@@ -4,80 +4,185 @@
 pub use crate::error::Error;
 pub use embedded_io_async::{ErrorType, Read, Write};
 
-const VARINT_CONTINUATION_BIT_MASK: u8 = 0b1000_0000;
+use core::convert::Infallible;
 
-pub async fn read_u8<R: Read>(input: &mut R) -> Result<u8, Error<R::Error>> {
-    let mut buf = [0u8; 1];
-    input.read_exact(&mut buf).await?;
-    Ok(buf[0])
-}
+const VARINT_CONTINUATION_BIT_MASK: u8 = 0b1000_0000;
 
-pub async fn read_u16<R: Read>(input: &mut R) -> Result<u16, Error<R::Error>> {
-    let mut buf = [0u8; 2];
-    input.read_exact(&mut buf).await?;
-    Ok(u16::from_be_bytes(buf))
-}
+/// Define functions generic over an IO trait, e.g. `fn read_u8, read_u8_blocking<R: Read>(...)`,
+/// once as `async fn` over the `embedded_io_async` trait and once, under the second name, as
+/// blocking `fn` over the `embedded_io` trait, so both variants share one body.
+///
+/// In the body, IO calls are wrapped in `io!(...)`, which awaits them in the async variant. Calls
+/// to other functions defined this way are written as `io!(async_name / blocking_name (args))`.
+macro_rules! async_and_blocking {
+    (@items ($d:tt) $(
+        $(#[$attr:meta])*
+        $vis:vis fn $name:ident, $blocking:ident <$io:ident: $trait:ident>
+            ($($params:tt)*) -> $ret:ty $body:block
+    )*) => {$(
+        $(#[$attr])*
+        $vis async fn $name<$io: embedded_io_async::$trait>($($params)*) -> $ret {
+            #[allow(unused_macros)]
+            macro_rules! io {
+                ($d f:ident / $d blocking:ident ($d($d args:tt)*)) => {
+                    $d f($d($d args)*).await
+                };
+                ($d e:expr) => {
+                    $d e.await
+                };
+            }
+            $body
+        }
 
-pub async fn read_u32<R: Read>(input: &mut R) -> Result<u32, Error<R::Error>> {
-    let mut buf = [0u8; 4];
-    input.read_exact(&mut buf).await?;
-    Ok(u32::from_be_bytes(buf))
+        #[doc = concat!("Blocking variant of `", stringify!($name), "`.")]
+        $vis fn $blocking<$io: embedded_io::$trait>($($params)*) -> $ret {
+            #[allow(unused_macros)]
+            macro_rules! io {
+                ($d f:ident / $d blocking:ident ($d($d args:tt)*)) => {
+                    $d blocking($d($d args)*)
+                };
+                ($d e:expr) => {
+                    $d e
+                };
+            }
+            $body
+        }
+    )*};
+    ($($items:tt)*) => {
+        $crate::packet::data_representation::async_and_blocking!(@items ($) $($items)*);
+    };
 }
+pub(crate) use async_and_blocking;
 
-pub async fn read_variable_byte_integer<R: Read>(input: &mut R) -> Result<u32, Error<R::Error>> {
-    let mut buf = [0u8; 1];
+async_and_blocking! {
+    pub fn read_u8, read_u8_blocking<R: Read>(input: &mut R) -> Result<u8, Error<R::Error>> {
+        let mut buf = [0u8; 1];
+        io!(input.read_exact(&mut buf))?;
+        Ok(buf[0])
+    }
 
-    // The following algorithm is adapted from MQTT5 specification section 1.5.5
-    let mut multiplier = 1u32;
-    let mut value = 0u32;
+    pub fn read_u16, read_u16_blocking<R: Read>(input: &mut R) -> Result<u16, Error<R::Error>> {
+        let mut buf = [0u8; 2];
+        io!(input.read_exact(&mut buf))?;
+        Ok(u16::from_be_bytes(buf))
+    }
 
-    loop {
-        input.read_exact(&mut buf).await?;
-        let encoded_byte = buf[0];
-        value += u32::from(encoded_byte & !VARINT_CONTINUATION_BIT_MASK) * multiplier;
+    pub fn read_u32, read_u32_blocking<R: Read>(input: &mut R) -> Result<u32, Error<R::Error>> {
+        let mut buf = [0u8; 4];
+        io!(input.read_exact(&mut buf))?;
+        Ok(u32::from_be_bytes(buf))
+    }
 
-        if encoded_byte & VARINT_CONTINUATION_BIT_MASK == 0 {
-            // Continuation bit is not set, this is the last byte.
-            break;
+    pub fn read_variable_byte_integer, read_variable_byte_integer_blocking<R: Read>(
+        input: &mut R,
+    ) -> Result<u32, Error<R::Error>> {
+        let mut decoder = VariableByteIntegerDecoder::new();
+        loop {
+            let encoded_byte = io!(read_u8 / read_u8_blocking(input))?;
+            if let Some(value) = decoder.push(encoded_byte).map_err(Error::widen)? {
+                return Ok(value);
+            }
         }
+    }
 
-        multiplier *= 128;
-        if multiplier > 128 * 128 * 128 {
-            // This would be the 5th byte, but the specification allows four bytes maximum.
-            return Err(Error::MalformedPacket);
-        }
+    pub fn write_u8, write_u8_blocking<W: Write>(
+        num: u8,
+        output: &mut W,
+    ) -> Result<(), Error<W::Error>> {
+        io!(output.write_all(&[num])).map_err(Error::NetworkError)
+    }
+
+    pub fn write_u16, write_u16_blocking<W: Write>(
+        num: u16,
+        output: &mut W,
+    ) -> Result<(), Error<W::Error>> {
+        io!(output.write_all(&num.to_be_bytes())).map_err(Error::NetworkError)
+    }
+
+    pub fn write_u32, write_u32_blocking<W: Write>(
+        num: u32,
+        output: &mut W,
+    ) -> Result<(), Error<W::Error>> {
+        io!(output.write_all(&num.to_be_bytes())).map_err(Error::NetworkError)
+    }
+
+    pub fn write_variable_byte_integer, write_variable_byte_integer_blocking<W: Write>(
+        num: u32,
+        output: &mut W,
+    ) -> Result<(), Error<W::Error>> {
+        let mut buf = [0u8; MAX_VARIABLE_BYTE_INTEGER_LEN];
+        let encoded = encode_variable_byte_integer(num, &mut buf).map_err(Error::widen)?;
+        io!(output.write_all(encoded)).map_err(Error::NetworkError)
     }
 
-    Ok(value)
+    pub fn write_utf8_string, write_utf8_string_blocking<W: Write>(
+        s: &str,
+        output: &mut W,
+    ) -> Result<(), Error<W::Error>> {
+        // UTF-8 encoded strings are prefixed with their length in bytes as a two byte integer.
+        let len: u16 = s.len().try_into().map_err(|_| Error::MalformedPacket)?;
+        io!(write_u16 / write_u16_blocking(len, output))?;
+        io!(output.write_all(s.as_bytes())).map_err(Error::NetworkError)
+    }
+}
+
+// The following functions parse from a slice that is already in memory, such as a datagram,
+// advancing the slice past the parsed data.
+
+pub(crate) fn take<'a>(input: &mut &'a [u8], len: usize) -> Result<&'a [u8], Error<Infallible>> {
+    if input.len() < len {
+        return Err(Error::MalformedPacket);
+    }
+    let (taken, rest) = input.split_at(len);
+    *input = rest;
+    Ok(taken)
 }
 
-pub async fn write_u8<W: Write>(num: u8, output: &mut W) -> Result<(), Error<W::Error>> {
-    output
-        .write_all(&[num])
-        .await
-        .map_err(|e| Error::NetworkError(e))
+pub(crate) fn take_u8(input: &mut &[u8]) -> Result<u8, Error<Infallible>> {
+    Ok(take(input, 1)?[0])
 }
 
-pub async fn write_u16<W: Write>(num: u16, output: &mut W) -> Result<(), Error<W::Error>> {
-    output
-        .write_all(&num.to_be_bytes())
-        .await
-        .map_err(|e| Error::NetworkError(e))
+pub(crate) fn take_u16(input: &mut &[u8]) -> Result<u16, Error<Infallible>> {
+    let bytes = take(input, 2)?;
+    Ok(u16::from_be_bytes([bytes[0], bytes[1]]))
 }
 
-pub async fn write_u32<W: Write>(num: u32, output: &mut W) -> Result<(), Error<W::Error>> {
-    output
-        .write_all(&num.to_be_bytes())
-        .await
-        .map_err(|e| Error::NetworkError(e))
+pub(crate) fn take_utf8_string<'a>(input: &mut &'a [u8]) -> Result<&'a str, Error<Infallible>> {
+    let len = take_u16(input)?;
+    let bytes = take(input, usize::from(len))?;
+    core::str::from_utf8(bytes).map_err(|_| Error::MalformedPacket)
 }
 
-pub async fn write_variable_byte_integer<W: Write>(
+pub(crate) fn take_variable_byte_integer(input: &mut &[u8]) -> Result<u32, Error<Infallible>> {
+    let mut decoder = VariableByteIntegerDecoder::new();
+    loop {
+        if let Some(value) = decoder.push(take_u8(input)?)? {
+            return Ok(value);
+        }
+    }
+}
+
+// The following items are the IO-independent core of the variable byte integer codec, shared by
+// the async, blocking and slice-based functions.
+
+/// Largest number of bytes a variable byte integer is encoded in.
+pub const MAX_VARIABLE_BYTE_INTEGER_LEN: usize = 4;
+/// Largest value that can be encoded as variable byte integer.
+pub const MAX_VARIABLE_BYTE_INTEGER: u32 = 268_435_455;
+
+/// Encode the number into the buffer, returning the part of the buffer holding the encoding.
+///
+/// Fails for numbers above [`MAX_VARIABLE_BYTE_INTEGER`].
+pub fn encode_variable_byte_integer(
     mut num: u32,
-    output: &mut W,
-) -> Result<(), Error<W::Error>> {
-    // The following algorithm is adapted from MQTT5 specification section 1.5.5
+    buf: &mut [u8; MAX_VARIABLE_BYTE_INTEGER_LEN],
+) -> Result<&[u8], Error<Infallible>> {
+    if num > MAX_VARIABLE_BYTE_INTEGER {
+        return Err(Error::MalformedPacket);
+    }
 
+    // The following algorithm is adapted from MQTT5 specification section 1.5.5
+    let mut len = 0;
     loop {
         let mut encoded_byte: u8 = (num % 128)
             .try_into()
@@ -88,19 +193,46 @@ pub async fn write_variable_byte_integer<W: Write>(
         if num > 0 {
             encoded_byte |= VARINT_CONTINUATION_BIT_MASK;
         }
-
-        output
-            .write_all(&[encoded_byte])
-            .await
-            .map_err(|e| Error::NetworkError(e))?;
+        buf[len] = encoded_byte;
+        len += 1;
 
         if num == 0 {
             // All bits encoded, we are done.
-            break;
+            return Ok(&buf[..len]);
         }
     }
+}
+
+/// Decodes a variable byte integer fed to it one byte at a time, so the bytes can come from any
+/// source and arrive in pieces.
+///
+/// Use a new decoder for each integer.
+#[derive(Debug, Default)]
+pub struct VariableByteIntegerDecoder {
+    value: u32,
+    len: u8,
+}
+
+impl VariableByteIntegerDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed the next byte, returning the value once its last byte was fed.
+    pub fn push(&mut self, encoded_byte: u8) -> Result<Option<u32>, Error<Infallible>> {
+        self.value |= u32::from(encoded_byte & !VARINT_CONTINUATION_BIT_MASK) << (7 * self.len);
+        self.len += 1;
 
-    Ok(())
+        if encoded_byte & VARINT_CONTINUATION_BIT_MASK == 0 {
+            // Continuation bit is not set, this is the last byte.
+            return Ok(Some(self.value));
+        }
+        if usize::from(self.len) == MAX_VARIABLE_BYTE_INTEGER_LEN {
+            // There would be a 5th byte, but the specification allows four bytes maximum.
+            return Err(Error::MalformedPacket);
+        }
+        Ok(None)
+    }
 }
 
 #[cfg(test)]
@@ -358,6 +490,22 @@ mod tests {
         }
     }
 
+    #[tokio::test]
+    async fn test_write_variable_byte_integer_too_large() {
+        let mut buffer = [0u8; 8];
+        let mut writer = &mut buffer[..];
+        let result = write_variable_byte_integer(MAX_VARIABLE_BYTE_INTEGER + 1, &mut writer).await;
+        assert!(matches!(result, Err(Error::MalformedPacket)));
+    }
+
+    #[test]
+    fn test_variable_byte_integer_decoder_in_pieces() {
+        let mut decoder = VariableByteIntegerDecoder::new();
+        assert!(matches!(decoder.push(0xFF), Ok(None)));
+        assert!(matches!(decoder.push(0xFF), Ok(None)));
+        assert!(matches!(decoder.push(0x7F), Ok(Some(2097151))));
+    }
+
     // Round-trip test for variable byte integer to ensure encoding/decoding consistency
     #[tokio::test]
     async fn test_variable_byte_integer_roundtrip() {
@@ -377,4 +525,85 @@ mod tests {
             assert_eq!(value, read_value, "Roundtrip failed for value {}", value);
         }
     }
+
+    #[tokio::test]
+    async fn test_write_utf8_string_success() {
+        let mut buffer = [0u8; 5];
+        let mut writer = &mut buffer[..];
+        write_utf8_string("abc", &mut writer).await.unwrap();
+        assert_eq!(buffer, [0x00, 0x03, b'a', b'b', b'c']);
+    }
+
+    #[tokio::test]
+    async fn test_write_utf8_string_empty() {
+        let mut buffer = [u8::MAX; 2];
+        let mut writer = &mut buffer[..];
+        write_utf8_string("", &mut writer).await.unwrap();
+        assert_eq!(buffer, [0x00, 0x00]);
+    }
+
+    #[tokio::test]
+    async fn test_write_utf8_string_too_long() {
+        let s = "a".repeat(usize::from(u16::MAX) + 1);
+        let mut buffer = [0u8; 4];
+        let mut writer = &mut buffer[..];
+        let result = write_utf8_string(&s, &mut writer).await;
+        assert!(matches!(result, Err(Error::MalformedPacket)));
+    }
+
+    #[test]
+    fn test_blocking_roundtrip() {
+        let mut buffer = [0u8; 16];
+        let mut writer = &mut buffer[..];
+        write_u8_blocking(0x42, &mut writer).unwrap();
+        write_u16_blocking(0x1234, &mut writer).unwrap();
+        write_u32_blocking(0x12345678, &mut writer).unwrap();
+        write_variable_byte_integer_blocking(16384, &mut writer).unwrap();
+        write_utf8_string_blocking("ab", &mut writer).unwrap();
+        assert_eq!(
+            buffer[..14],
+            [
+                0x42, 0x12, 0x34, 0x12, 0x34, 0x56, 0x78, 0x80, 0x80, 0x01, 0x00, 0x02, b'a', b'b'
+            ]
+        );
+
+        let mut reader = &buffer[..];
+        assert_eq!(read_u8_blocking(&mut reader).unwrap(), 0x42);
+        assert_eq!(read_u16_blocking(&mut reader).unwrap(), 0x1234);
+        assert_eq!(read_u32_blocking(&mut reader).unwrap(), 0x12345678);
+        assert_eq!(
+            read_variable_byte_integer_blocking(&mut reader).unwrap(),
+            16384
+        );
+        assert_eq!(take_utf8_string(&mut reader).unwrap(), "ab");
+    }
+
+    #[test]
+    fn test_blocking_errors() {
+        let mut reader = &[0x12][..];
+        assert!(matches!(
+            read_u16_blocking(&mut reader),
+            Err(Error::MalformedPacket)
+        ));
+
+        let s = "a".repeat(usize::from(u16::MAX) + 1);
+        let mut buffer = [0u8; 4];
+        let mut writer = &mut buffer[..];
+        assert!(matches!(
+            write_utf8_string_blocking(&s, &mut writer),
+            Err(Error::MalformedPacket)
+        ));
+        assert!(matches!(
+            write_u32_blocking(0, &mut &mut buffer[..3]),
+            Err(Error::NetworkError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_write_utf8_string_buffer_too_small() {
+        let mut buffer = [0u8; 4];
+        let mut writer = &mut buffer[..];
+        let result = write_utf8_string("abc", &mut writer).await;
+        assert!(matches!(result, Err(Error::NetworkError(_))));
+    }
 }
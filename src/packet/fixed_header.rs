@@ -1,9 +1,17 @@
 //! This module deals with the MQTT fixed header and its fields.
 
-use crate::{error::Error, packet::data_representation};
-use embedded_io_async::{Read, Write};
+use crate::{
+    error::Error,
+    packet::data_representation::{
+        self, VariableByteIntegerDecoder, async_and_blocking, read_u8, read_u8_blocking,
+    },
+};
+use core::convert::Infallible;
 
-#[derive(Debug)]
+/// Largest number of bytes a fixed header is encoded in.
+pub const MAX_ENCODED_LEN: usize = 1 + data_representation::MAX_VARIABLE_BYTE_INTEGER_LEN;
+
+#[derive(Debug, Clone, Copy)]
 pub struct FixedHeader {
     type_: PacketType,
     flags: u8,
@@ -11,27 +19,94 @@ pub struct FixedHeader {
 }
 
 impl FixedHeader {
-    pub async fn read<R: Read>(input: &mut R) -> Result<Self, Error<R::Error>> {
-        let control_byte = data_representation::read_u8(input).await?;
-        let type_ = PacketType::from_bits(control_byte >> 4);
-        let flags = control_byte & 0b0000_1111;
-        let remaining_length = data_representation::read_variable_byte_integer(input).await?;
+    pub fn packet_type(&self) -> PacketType {
+        self.type_
+    }
+
+    /// The flags in the lower half of the control byte, whose meaning depends on the packet type.
+    pub fn flags(&self) -> u8 {
+        self.flags
+    }
+
+    /// Length of the packet following the fixed header, in bytes.
+    pub fn remaining_length(&self) -> u32 {
+        self.remaining_length
+    }
+
+    async_and_blocking! {
+        /// Read a fixed header.
+        ///
+        /// This is not cancellation safe: if the future is dropped after reading part of the
+        /// header, those bytes are lost. Use [`PacketReader`](super::reader::PacketReader) where
+        /// reads may be cancelled, e.g. in a `select!`.
+        pub fn read, read_blocking<R: Read>(input: &mut R) -> Result<Self, Error<R::Error>> {
+            let mut decoder = FixedHeaderDecoder::new();
+            loop {
+                let byte = io!(read_u8 / read_u8_blocking(input))?;
+                if let Some(header) = decoder.push(byte).map_err(Error::widen)? {
+                    return Ok(header);
+                }
+            }
+        }
 
-        Ok(Self {
-            type_,
-            flags,
-            remaining_length,
-        })
+        pub fn write, write_blocking<W: Write>(&self, output: &mut W) -> Result<(), Error<W::Error>> {
+            let mut buf = [0u8; MAX_ENCODED_LEN];
+            let encoded = self.encode(&mut buf).map_err(Error::widen)?;
+            io!(output.write_all(encoded)).map_err(Error::NetworkError)
+        }
     }
 
-    pub async fn write<W: Write>(&self, output: &mut W) -> Result<(), Error<W::Error>> {
-        let control_byte = (self.type_.to_bits() << 4) | (self.flags & 0b0000_1111);
-        data_representation::write_u8(control_byte, output).await?;
-        data_representation::write_variable_byte_integer(self.remaining_length, output).await
+    /// Encode into the buffer, returning the part of the buffer holding the encoding.
+    pub fn encode<'b>(
+        &self,
+        buf: &'b mut [u8; MAX_ENCODED_LEN],
+    ) -> Result<&'b [u8], Error<Infallible>> {
+        let mut remaining_length = [0u8; data_representation::MAX_VARIABLE_BYTE_INTEGER_LEN];
+        let remaining_length = data_representation::encode_variable_byte_integer(
+            self.remaining_length,
+            &mut remaining_length,
+        )?;
+
+        buf[0] = (self.type_.to_bits() << 4) | (self.flags & 0b0000_1111);
+        buf[1..1 + remaining_length.len()].copy_from_slice(remaining_length);
+        Ok(&buf[..1 + remaining_length.len()])
     }
 }
 
-#[derive(Debug)]
+/// Decodes a fixed header fed to it one byte at a time, the IO-independent core of
+/// [`FixedHeader::read`] and [`FixedHeader::read_blocking`].
+///
+/// Use a new decoder for each header.
+#[derive(Debug, Default)]
+pub struct FixedHeaderDecoder {
+    control_byte: Option<u8>,
+    remaining_length: VariableByteIntegerDecoder,
+}
+
+impl FixedHeaderDecoder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Feed the next byte, returning the header once its last byte was fed.
+    pub fn push(&mut self, byte: u8) -> Result<Option<FixedHeader>, Error<Infallible>> {
+        let Some(control_byte) = self.control_byte else {
+            self.control_byte = Some(byte);
+            return Ok(None);
+        };
+
+        Ok(self
+            .remaining_length
+            .push(byte)?
+            .map(|remaining_length| FixedHeader {
+                type_: PacketType::from_bits(control_byte >> 4),
+                flags: control_byte & 0b0000_1111,
+                remaining_length,
+            }))
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub enum PacketType {
     Reserved,
     Connect,
@@ -184,6 +259,35 @@ mod tests {
         assert!(matches!(result, Err(Error::MalformedPacket)));
     }
 
+    #[test]
+    fn test_fixed_header_blocking_roundtrip() {
+        let header = FixedHeader {
+            type_: PacketType::Subscribe,
+            flags: 0b0010,
+            remaining_length: 321,
+        };
+
+        let mut buffer = [0u8; MAX_ENCODED_LEN];
+        let mut writer = &mut buffer[..];
+        header.write_blocking(&mut writer).unwrap();
+        assert_eq!(buffer[..3], [0b10000010, 0xC1, 0x02]);
+
+        let mut reader = &buffer[..];
+        let read = FixedHeader::read_blocking(&mut reader).unwrap();
+        assert!(matches!(read.type_, PacketType::Subscribe));
+        assert_eq!(read.flags, 0b0010);
+        assert_eq!(read.remaining_length, 321);
+    }
+
+    #[test]
+    fn test_fixed_header_decoder_rejects_long_remaining_length() {
+        let mut decoder = FixedHeaderDecoder::new();
+        for byte in [0x30, 0xFF, 0xFF, 0xFF] {
+            assert!(matches!(decoder.push(byte), Ok(None)));
+        }
+        assert!(matches!(decoder.push(0xFF), Err(Error::MalformedPacket)));
+    }
+
     // Tests for FixedHeader::write()
     #[tokio::test]
     async fn test_fixed_header_write_success() {
@@ -2,3 +2,7 @@
 
 pub mod data_representation;
 pub mod fixed_header;
+pub mod properties;
+pub mod qos;
+pub mod reader;
+pub mod subscribe;
@@ -0,0 +1,289 @@
+//! This module deals with the properties contained in the variable header of MQTT5 packets.
+
+use crate::{
+    error::Error,
+    packet::data_representation::{
+        async_and_blocking, take, take_u16, take_utf8_string, take_variable_byte_integer,
+        write_utf8_string, write_utf8_string_blocking, write_variable_byte_integer,
+        write_variable_byte_integer_blocking,
+    },
+};
+use core::convert::Infallible;
+
+/// Property identifier of the User Property.
+pub const USER_PROPERTY: u8 = 0x26;
+
+/// A bounded list of up to `N` User Properties to attach to an outgoing packet.
+#[derive(Debug)]
+pub struct UserProperties<'a, const N: usize> {
+    properties: [(&'a str, &'a str); N],
+    len: usize,
+}
+
+impl<'a, const N: usize> UserProperties<'a, N> {
+    pub fn new() -> Self {
+        Self {
+            properties: [("", ""); N],
+            len: 0,
+        }
+    }
+
+    /// Add a key/value pair.
+    ///
+    /// If the list is already full, the pair is handed back as the error.
+    pub fn push(&mut self, key: &'a str, value: &'a str) -> Result<(), (&'a str, &'a str)> {
+        match self.properties.get_mut(self.len) {
+            Some(slot) => {
+                *slot = (key, value);
+                self.len += 1;
+                Ok(())
+            }
+            None => Err((key, value)),
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = (&'a str, &'a str)> + '_ {
+        self.properties[..self.len].iter().copied()
+    }
+
+    /// Number of bytes the properties take up in the property section of a packet.
+    pub fn encoded_len(&self) -> usize {
+        // Each property consists of its identifier and two length-prefixed strings.
+        self.iter()
+            .map(|(key, value)| 1 + 2 + key.len() + 2 + value.len())
+            .sum()
+    }
+
+    async_and_blocking! {
+        pub fn write, write_blocking<W: Write>(&self, output: &mut W) -> Result<(), Error<W::Error>> {
+            for (key, value) in self.iter() {
+                io!(write_variable_byte_integer / write_variable_byte_integer_blocking(
+                    USER_PROPERTY.into(),
+                    output
+                ))?;
+                io!(write_utf8_string / write_utf8_string_blocking(key, output))?;
+                io!(write_utf8_string / write_utf8_string_blocking(value, output))?;
+            }
+
+            Ok(())
+        }
+    }
+}
+
+impl<const N: usize> Default for UserProperties<'_, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Iterator over the User Properties contained in the property section of a received packet.
+///
+/// All other properties are skipped. If the property section is malformed, the iterator yields
+/// [`Error::MalformedPacket`] once and then stops.
+#[derive(Debug)]
+pub struct UserPropertiesIter<'a> {
+    properties: &'a [u8],
+}
+
+impl<'a> UserPropertiesIter<'a> {
+    /// Create an iterator over the given property section, excluding the property length.
+    pub fn new(properties: &'a [u8]) -> Self {
+        Self { properties }
+    }
+
+    fn next_user_property(&mut self) -> Result<Option<(&'a str, &'a str)>, Error<Infallible>> {
+        while !self.properties.is_empty() {
+            let identifier = take_variable_byte_integer(&mut self.properties)?;
+            let value_len = match identifier {
+                // Payload Format Indicator, Request Problem Information, Request Response
+                // Information, Maximum QoS, Retain Available, Wildcard Subscription Available,
+                // Subscription Identifier Available, Shared Subscription Available
+                0x01 | 0x17 | 0x19 | 0x24 | 0x25 | 0x28 | 0x29 | 0x2A => 1,
+                // Server Keep Alive, Receive Maximum, Topic Alias Maximum, Topic Alias
+                0x13 | 0x21 | 0x22 | 0x23 => 2,
+                // Message Expiry Interval, Session Expiry Interval, Will Delay Interval,
+                // Maximum Packet Size
+                0x02 | 0x11 | 0x18 | 0x27 => 4,
+                // Subscription Identifier
+                0x0B => {
+                    take_variable_byte_integer(&mut self.properties)?;
+                    0
+                }
+                // Content Type, Response Topic, Assigned Client Identifier, Authentication
+                // Method, Response Information, Server Reference, Reason String (UTF-8 strings),
+                // Correlation Data, Authentication Data (binary data)
+                0x03 | 0x08 | 0x12 | 0x15 | 0x1A | 0x1C | 0x1F | 0x09 | 0x16 => {
+                    let len = take_u16(&mut self.properties)?;
+                    usize::from(len)
+                }
+                0x26 => {
+                    let key = take_utf8_string(&mut self.properties)?;
+                    let value = take_utf8_string(&mut self.properties)?;
+                    return Ok(Some((key, value)));
+                }
+                _ => return Err(Error::MalformedPacket),
+            };
+            take(&mut self.properties, value_len)?;
+        }
+
+        Ok(None)
+    }
+}
+
+impl<'a> Iterator for UserPropertiesIter<'a> {
+    type Item = Result<(&'a str, &'a str), Error<Infallible>>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let result = self.next_user_property();
+        if result.is_err() {
+            // Stop iterating after reporting the malformed property section.
+            self.properties = &[];
+        }
+        result.transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_user_properties_push() {
+        let mut properties = UserProperties::<2>::new();
+        assert!(properties.is_empty());
+        assert_eq!(properties.push("a", "1"), Ok(()));
+        assert_eq!(properties.push("b", "2"), Ok(()));
+        assert_eq!(properties.push("c", "3"), Err(("c", "3")));
+        assert_eq!(properties.len(), 2);
+
+        let mut iter = properties.iter();
+        assert_eq!(iter.next(), Some(("a", "1")));
+        assert_eq!(iter.next(), Some(("b", "2")));
+        assert_eq!(iter.next(), None);
+    }
+
+    #[tokio::test]
+    async fn test_user_properties_write() {
+        let mut properties = UserProperties::<2>::new();
+        properties.push("a", "1").unwrap();
+        properties.push("bc", "").unwrap();
+        assert_eq!(properties.encoded_len(), 14);
+
+        let mut buffer = [0u8; 14];
+        let mut writer = &mut buffer[..];
+        properties.write(&mut writer).await.unwrap();
+        assert_eq!(
+            buffer,
+            [
+                0x26, 0x00, 0x01, b'a', 0x00, 0x01, b'1', //
+                0x26, 0x00, 0x02, b'b', b'c', 0x00, 0x00,
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_user_properties_write_buffer_too_small() {
+        let mut properties = UserProperties::<1>::new();
+        properties.push("a", "1").unwrap();
+
+        let mut buffer = [0u8; 6];
+        let mut writer = &mut buffer[..];
+        let result = properties.write(&mut writer).await;
+        assert!(matches!(result, Err(Error::NetworkError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_user_properties_roundtrip() {
+        let mut properties = UserProperties::<3>::new();
+        properties.push("key", "value").unwrap();
+        properties.push("key", "other value").unwrap();
+        properties.push("", "").unwrap();
+
+        let mut buffer = [0u8; 64];
+        let mut writer = &mut buffer[..];
+        properties.write(&mut writer).await.unwrap();
+
+        let encoded = &buffer[..properties.encoded_len()];
+        let mut iter = UserPropertiesIter::new(encoded);
+        assert_eq!(iter.next().unwrap().unwrap(), ("key", "value"));
+        assert_eq!(iter.next().unwrap().unwrap(), ("key", "other value"));
+        assert_eq!(iter.next().unwrap().unwrap(), ("", ""));
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn test_user_properties_write_blocking() {
+        let mut properties = UserProperties::<2>::new();
+        properties.push("a", "1").unwrap();
+        properties.push("bc", "").unwrap();
+
+        let mut buffer = [0u8; 14];
+        let mut writer = &mut buffer[..];
+        properties.write_blocking(&mut writer).unwrap();
+        assert_eq!(
+            buffer,
+            [
+                0x26, 0x00, 0x01, b'a', 0x00, 0x01, b'1', //
+                0x26, 0x00, 0x02, b'b', b'c', 0x00, 0x00,
+            ]
+        );
+
+        let mut writer = &mut buffer[..13];
+        let result = properties.write_blocking(&mut writer);
+        assert!(matches!(result, Err(Error::NetworkError(_))));
+    }
+
+    #[test]
+    fn test_user_properties_iter_skips_other_properties() {
+        let data = [
+            0x01, 0x01, // Payload Format Indicator
+            0x26, 0x00, 0x01, b'a', 0x00, 0x01, b'1', // User Property
+            0x02, 0x00, 0x00, 0x00, 0x3C, // Message Expiry Interval
+            0x0B, 0x80, 0x01, // Subscription Identifier
+            0x03, 0x00, 0x02, b'{', b'}', // Content Type
+            0x23, 0x00, 0x05, // Topic Alias
+            0x26, 0x00, 0x01, b'b', 0x00, 0x01, b'2', // User Property
+        ];
+        let mut iter = UserPropertiesIter::new(&data);
+        assert_eq!(iter.next().unwrap().unwrap(), ("a", "1"));
+        assert_eq!(iter.next().unwrap().unwrap(), ("b", "2"));
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn test_user_properties_iter_empty() {
+        let mut iter = UserPropertiesIter::new(&[]);
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn test_user_properties_iter_unknown_identifier() {
+        let data = [0x7F, 0x00];
+        let mut iter = UserPropertiesIter::new(&data);
+        assert!(matches!(iter.next(), Some(Err(Error::MalformedPacket))));
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn test_user_properties_iter_truncated() {
+        let data = [0x26, 0x00, 0x01, b'a', 0x00, 0x05, b'1'];
+        let mut iter = UserPropertiesIter::new(&data);
+        assert!(matches!(iter.next(), Some(Err(Error::MalformedPacket))));
+        assert!(iter.next().is_none());
+    }
+
+    #[test]
+    fn test_user_properties_iter_invalid_utf8() {
+        let data = [0x26, 0x00, 0x01, 0xFF, 0x00, 0x00];
+        let mut iter = UserPropertiesIter::new(&data);
+        assert!(matches!(iter.next(), Some(Err(Error::MalformedPacket))));
+    }
+}
@@ -0,0 +1,52 @@
+//! This module contains the Quality of Service levels used for message delivery.
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QoS {
+    AtMostOnce,
+    AtLeastOnce,
+    ExactlyOnce,
+}
+
+impl QoS {
+    /// Convert to the raw 2-bit unsigned value that represents the given QoS level.
+    pub fn to_bits(&self) -> u8 {
+        match self {
+            QoS::AtMostOnce => 0,
+            QoS::AtLeastOnce => 1,
+            QoS::ExactlyOnce => 2,
+        }
+    }
+
+    /// Get the [`QoS`] that the given bits represent.
+    ///
+    /// Returns `None` for the reserved value 3 or any larger value.
+    pub fn from_bits(bits: u8) -> Option<Self> {
+        match bits {
+            0 => Some(QoS::AtMostOnce),
+            1 => Some(QoS::AtLeastOnce),
+            2 => Some(QoS::ExactlyOnce),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_qos_to_bits() {
+        assert_eq!(QoS::AtMostOnce.to_bits(), 0);
+        assert_eq!(QoS::AtLeastOnce.to_bits(), 1);
+        assert_eq!(QoS::ExactlyOnce.to_bits(), 2);
+    }
+
+    #[test]
+    fn test_qos_from_bits() {
+        assert_eq!(QoS::from_bits(0), Some(QoS::AtMostOnce));
+        assert_eq!(QoS::from_bits(1), Some(QoS::AtLeastOnce));
+        assert_eq!(QoS::from_bits(2), Some(QoS::ExactlyOnce));
+        assert_eq!(QoS::from_bits(3), None);
+        assert_eq!(QoS::from_bits(0b0000_0101), None);
+    }
+}
@@ -0,0 +1,235 @@
+//! This module contains a cancellation-safe reader for whole control packets.
+
+use crate::{
+    error::Error,
+    packet::fixed_header::{FixedHeader, FixedHeaderDecoder},
+};
+use embedded_io_async::Read;
+
+/// A control packet read by a [`PacketReader`].
+#[derive(Debug)]
+pub struct Packet<'a> {
+    pub header: FixedHeader,
+    /// The `remaining_length` bytes following the fixed header.
+    pub body: &'a [u8],
+}
+
+/// Reads control packets with a body of up to `N` bytes.
+///
+/// All progress is kept in the reader rather than in the future returned by
+/// [`PacketReader::read`], so dropping that future, e.g. because another branch of a `select!`
+/// completed, loses no data as long as the transport's `read` is cancellation safe. Calling
+/// `read` again resumes where the dropped future stopped.
+#[derive(Debug)]
+pub struct PacketReader<const N: usize> {
+    decoder: FixedHeaderDecoder,
+    header: Option<FixedHeader>,
+    body: [u8; N],
+    filled: usize,
+}
+
+impl<const N: usize> PacketReader<N> {
+    pub fn new() -> Self {
+        Self {
+            decoder: FixedHeaderDecoder::new(),
+            header: None,
+            body: [0; N],
+            filled: 0,
+        }
+    }
+
+    /// Read the next packet, or the rest of it if a previous call was cancelled.
+    ///
+    /// A packet with a body larger than `N` bytes is treated as malformed. After an error, the
+    /// stream is no longer aligned to packet boundaries and the connection should be closed.
+    pub async fn read<R: Read>(&mut self, input: &mut R) -> Result<Packet<'_>, Error<R::Error>> {
+        let result = self.read_inner(input).await;
+        if result.is_err() {
+            self.reset();
+        }
+        let header = result?;
+
+        let len = header.remaining_length() as usize;
+        self.reset();
+        Ok(Packet {
+            header,
+            body: &self.body[..len],
+        })
+    }
+
+    async fn read_inner<R: Read>(&mut self, input: &mut R) -> Result<FixedHeader, Error<R::Error>> {
+        let header = match self.header {
+            Some(header) => header,
+            None => {
+                let header = loop {
+                    // One byte at a time, so no byte following the header is consumed.
+                    let mut byte = [0u8; 1];
+                    read_some(input, &mut byte).await?;
+                    if let Some(header) = self.decoder.push(byte[0]).map_err(Error::widen)? {
+                        break header;
+                    }
+                };
+                if header.remaining_length() as usize > N {
+                    return Err(Error::MalformedPacket);
+                }
+                self.header = Some(header);
+                header
+            }
+        };
+
+        let len = header.remaining_length() as usize;
+        while self.filled < len {
+            self.filled += read_some(input, &mut self.body[self.filled..len]).await?;
+        }
+        Ok(header)
+    }
+
+    fn reset(&mut self) {
+        self.decoder = FixedHeaderDecoder::new();
+        self.header = None;
+        self.filled = 0;
+    }
+}
+
+impl<const N: usize> Default for PacketReader<N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Read at least one byte, treating the end of the stream as a truncated packet.
+async fn read_some<R: Read>(input: &mut R, buf: &mut [u8]) -> Result<usize, Error<R::Error>> {
+    match input.read(buf).await.map_err(Error::NetworkError)? {
+        0 => Err(Error::MalformedPacket),
+        n => Ok(n),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::fixed_header::PacketType;
+    use core::{
+        future::Future,
+        pin::pin,
+        task::{Context, Poll, Waker},
+    };
+    use embedded_io_async::ErrorType;
+
+    /// Returns one chunk per read, and `Poll::Pending` once before each chunk, so reads can be
+    /// cancelled between chunks.
+    struct Chunks<'a> {
+        chunks: &'a [&'a [u8]],
+        offset: usize,
+        ready: bool,
+    }
+
+    impl ErrorType for Chunks<'_> {
+        type Error = core::convert::Infallible;
+    }
+
+    impl Read for Chunks<'_> {
+        async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+            core::future::poll_fn(|cx| {
+                if self.ready {
+                    Poll::Ready(())
+                } else {
+                    self.ready = true;
+                    cx.waker().wake_by_ref();
+                    Poll::Pending
+                }
+            })
+            .await;
+            self.ready = false;
+
+            let Some((chunk, rest)) = self.chunks.split_first() else {
+                return Ok(0);
+            };
+            let len = buf.len().min(chunk.len() - self.offset);
+            buf[..len].copy_from_slice(&chunk[self.offset..self.offset + len]);
+            self.offset += len;
+            if self.offset == chunk.len() {
+                self.chunks = rest;
+                self.offset = 0;
+            }
+            Ok(len)
+        }
+    }
+
+    /// Poll the future once, dropping it if it isn't ready, like a `select!` whose other branch
+    /// completed first.
+    fn poll_once<F: Future>(future: F) -> Option<F::Output> {
+        let mut future = pin!(future);
+        match future
+            .as_mut()
+            .poll(&mut Context::from_waker(Waker::noop()))
+        {
+            Poll::Ready(output) => Some(output),
+            Poll::Pending => None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_read_packets() {
+        let chunks: &[&[u8]] = &[&[0xC0, 0x00, 0x30, 0x03, b'a', b'b', b'c']];
+        let mut input = Chunks {
+            chunks,
+            offset: 0,
+            ready: false,
+        };
+        let mut reader = PacketReader::<8>::new();
+
+        let packet = reader.read(&mut input).await.unwrap();
+        assert_eq!(packet.header.packet_type(), PacketType::PingReq);
+        assert_eq!(packet.body, []);
+
+        let packet = reader.read(&mut input).await.unwrap();
+        assert_eq!(packet.header.packet_type(), PacketType::Publish);
+        assert_eq!(packet.body, b"abc");
+
+        assert!(matches!(
+            reader.read(&mut input).await,
+            Err(Error::MalformedPacket)
+        ));
+    }
+
+    #[test]
+    fn test_read_resumes_after_cancellation() {
+        // The header and body arrive in pieces, with a chance to cancel before each.
+        let chunks: &[&[u8]] = &[&[0x30], &[0x83], &[0x01, b'x'], &[b'y'; 130]];
+        let mut input = Chunks {
+            chunks,
+            offset: 0,
+            ready: false,
+        };
+        let mut reader = PacketReader::<256>::new();
+
+        let mut cancelled = 0;
+        let packet = loop {
+            match poll_once(reader.read(&mut input)) {
+                Some(packet) => break packet.unwrap(),
+                None => cancelled += 1,
+            }
+        };
+        assert!(cancelled >= 4);
+        assert_eq!(packet.header.packet_type(), PacketType::Publish);
+        assert_eq!(packet.header.remaining_length(), 131);
+        assert_eq!(packet.body[0], b'x');
+        assert!(packet.body[1..].iter().all(|&b| b == b'y'));
+    }
+
+    #[tokio::test]
+    async fn test_read_rejects_oversized_packet() {
+        let chunks: &[&[u8]] = &[&[0x30, 0x05, 1, 2, 3, 4, 5]];
+        let mut input = Chunks {
+            chunks,
+            offset: 0,
+            ready: false,
+        };
+        let mut reader = PacketReader::<4>::new();
+        assert!(matches!(
+            reader.read(&mut input).await,
+            Err(Error::MalformedPacket)
+        ));
+    }
+}
@@ -0,0 +1,244 @@
+//! This module deals with the SUBSCRIBE packet and its fields.
+
+use crate::{
+    error::Error,
+    packet::data_representation::{
+        async_and_blocking, read_u8, read_u8_blocking, write_u8, write_u8_blocking,
+    },
+    packet::qos::QoS,
+};
+
+const QOS_MASK: u8 = 0b0000_0011;
+const NO_LOCAL_BIT: u8 = 0b0000_0100;
+const RETAIN_AS_PUBLISHED_BIT: u8 = 0b0000_1000;
+const RETAIN_HANDLING_SHIFT: u8 = 4;
+const RETAIN_HANDLING_MASK: u8 = 0b0011_0000;
+const RESERVED_MASK: u8 = 0b1100_0000;
+
+/// The options byte following each topic filter in a SUBSCRIBE packet.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubscriptionOptions {
+    /// Maximum QoS the broker may use when forwarding messages.
+    pub qos: QoS,
+    /// Don't forward messages published by this client back to it.
+    pub no_local: bool,
+    /// Keep the retain flag of forwarded messages as it was set by the publisher.
+    pub retain_as_published: bool,
+    /// Whether retained messages are sent when the subscription is established.
+    pub retain_handling: RetainHandling,
+}
+
+impl SubscriptionOptions {
+    /// Options with the given QoS and all other fields set to their MQTT 3.1.1 compatible
+    /// defaults.
+    pub fn new(qos: QoS) -> Self {
+        Self {
+            qos,
+            no_local: false,
+            retain_as_published: false,
+            retain_handling: RetainHandling::SendOnSubscribe,
+        }
+    }
+
+    pub fn to_byte(&self) -> u8 {
+        let mut byte = self.qos.to_bits();
+        if self.no_local {
+            byte |= NO_LOCAL_BIT;
+        }
+        if self.retain_as_published {
+            byte |= RETAIN_AS_PUBLISHED_BIT;
+        }
+        byte | (self.retain_handling.to_bits() << RETAIN_HANDLING_SHIFT)
+    }
+
+    /// Parse the options byte, rejecting reserved QoS and Retain Handling values as well as
+    /// set reserved bits.
+    pub fn from_byte(byte: u8) -> Option<Self> {
+        if byte & RESERVED_MASK != 0 {
+            return None;
+        }
+
+        Some(Self {
+            qos: QoS::from_bits(byte & QOS_MASK)?,
+            no_local: byte & NO_LOCAL_BIT != 0,
+            retain_as_published: byte & RETAIN_AS_PUBLISHED_BIT != 0,
+            retain_handling: RetainHandling::from_bits(
+                (byte & RETAIN_HANDLING_MASK) >> RETAIN_HANDLING_SHIFT,
+            )?,
+        })
+    }
+
+    async_and_blocking! {
+        pub fn read, read_blocking<R: Read>(input: &mut R) -> Result<Self, Error<R::Error>> {
+            let byte = io!(read_u8 / read_u8_blocking(input))?;
+            Self::from_byte(byte).ok_or(Error::MalformedPacket)
+        }
+
+        pub fn write, write_blocking<W: Write>(&self, output: &mut W) -> Result<(), Error<W::Error>> {
+            io!(write_u8 / write_u8_blocking(self.to_byte(), output))
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RetainHandling {
+    /// Send retained messages at the time of the subscribe.
+    SendOnSubscribe,
+    /// Send retained messages only if the subscription does not currently exist.
+    SendOnNewSubscribe,
+    /// Do not send retained messages at the time of the subscribe.
+    DoNotSend,
+}
+
+impl RetainHandling {
+    /// Convert to the raw 2-bit unsigned value that represents the given option.
+    pub fn to_bits(&self) -> u8 {
+        match self {
+            RetainHandling::SendOnSubscribe => 0,
+            RetainHandling::SendOnNewSubscribe => 1,
+            RetainHandling::DoNotSend => 2,
+        }
+    }
+
+    /// Get the [`RetainHandling`] that the given bits represent.
+    ///
+    /// Returns `None` for the reserved value 3 or any larger value.
+    pub fn from_bits(bits: u8) -> Option<Self> {
+        match bits {
+            0 => Some(RetainHandling::SendOnSubscribe),
+            1 => Some(RetainHandling::SendOnNewSubscribe),
+            2 => Some(RetainHandling::DoNotSend),
+            _ => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_subscription_options_to_byte() {
+        assert_eq!(SubscriptionOptions::new(QoS::AtMostOnce).to_byte(), 0);
+        assert_eq!(SubscriptionOptions::new(QoS::ExactlyOnce).to_byte(), 2);
+
+        let options = SubscriptionOptions {
+            qos: QoS::AtLeastOnce,
+            no_local: true,
+            retain_as_published: true,
+            retain_handling: RetainHandling::DoNotSend,
+        };
+        assert_eq!(options.to_byte(), 0b0010_1101);
+
+        let options = SubscriptionOptions {
+            qos: QoS::AtMostOnce,
+            no_local: false,
+            retain_as_published: true,
+            retain_handling: RetainHandling::SendOnNewSubscribe,
+        };
+        assert_eq!(options.to_byte(), 0b0001_1000);
+    }
+
+    #[test]
+    fn test_subscription_options_from_byte() {
+        assert_eq!(
+            SubscriptionOptions::from_byte(0b0010_1101),
+            Some(SubscriptionOptions {
+                qos: QoS::AtLeastOnce,
+                no_local: true,
+                retain_as_published: true,
+                retain_handling: RetainHandling::DoNotSend,
+            })
+        );
+        assert_eq!(
+            SubscriptionOptions::from_byte(0b0000_0100),
+            Some(SubscriptionOptions {
+                qos: QoS::AtMostOnce,
+                no_local: true,
+                retain_as_published: false,
+                retain_handling: RetainHandling::SendOnSubscribe,
+            })
+        );
+    }
+
+    #[test]
+    fn test_subscription_options_from_byte_rejects_reserved_values() {
+        // QoS 3
+        assert_eq!(SubscriptionOptions::from_byte(0b0000_0011), None);
+        // Retain Handling 3
+        assert_eq!(SubscriptionOptions::from_byte(0b0011_0000), None);
+        // Reserved bits
+        assert_eq!(SubscriptionOptions::from_byte(0b0100_0000), None);
+        assert_eq!(SubscriptionOptions::from_byte(0b1000_0000), None);
+    }
+
+    #[test]
+    fn test_subscription_options_roundtrip() {
+        for byte in 0..=u8::MAX {
+            if let Some(options) = SubscriptionOptions::from_byte(byte) {
+                assert_eq!(options.to_byte(), byte);
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn test_subscription_options_read_success() {
+        let data = [0b0001_1001];
+        let mut reader = &data[..];
+        let options = SubscriptionOptions::read(&mut reader).await.unwrap();
+        assert_eq!(options.qos, QoS::AtLeastOnce);
+        assert!(!options.no_local);
+        assert!(options.retain_as_published);
+        assert_eq!(options.retain_handling, RetainHandling::SendOnNewSubscribe);
+    }
+
+    #[tokio::test]
+    async fn test_subscription_options_read_reserved() {
+        let data = [0b1100_0000];
+        let mut reader = &data[..];
+        let result = SubscriptionOptions::read(&mut reader).await;
+        assert!(matches!(result, Err(Error::MalformedPacket)));
+    }
+
+    #[tokio::test]
+    async fn test_subscription_options_write_success() {
+        let options = SubscriptionOptions {
+            qos: QoS::ExactlyOnce,
+            no_local: true,
+            retain_as_published: false,
+            retain_handling: RetainHandling::DoNotSend,
+        };
+
+        let mut buffer = [0u8; 1];
+        let mut writer = &mut buffer[..];
+        options.write(&mut writer).await.unwrap();
+        assert_eq!(buffer, [0b0010_0110]);
+    }
+
+    #[test]
+    fn test_subscription_options_blocking_roundtrip() {
+        let options = SubscriptionOptions {
+            qos: QoS::AtLeastOnce,
+            no_local: true,
+            retain_as_published: true,
+            retain_handling: RetainHandling::SendOnNewSubscribe,
+        };
+
+        let mut buffer = [0u8; 1];
+        let mut writer = &mut buffer[..];
+        options.write_blocking(&mut writer).unwrap();
+        assert_eq!(buffer, [0b0001_1101]);
+
+        let mut reader = &buffer[..];
+        assert_eq!(
+            SubscriptionOptions::read_blocking(&mut reader).unwrap(),
+            options
+        );
+
+        let mut reader = &[0b1100_0000][..];
+        assert!(matches!(
+            SubscriptionOptions::read_blocking(&mut reader),
+            Err(Error::MalformedPacket)
+        ));
+    }
+}
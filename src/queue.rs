@@ -0,0 +1,254 @@
+//! This module contains a lock-free queue for handing small records, such as measurements, from
+//! interrupt handlers to the task that publishes them.
+//!
+//! The queue has a single producer and a single consumer, and pushing and popping only use atomic
+//! loads and stores, so it works on targets without compare-and-swap such as `thumbv6m`.
+//!
+//! A queue shared with interrupt handlers usually lives in a `static`, and is split once during
+//! initialization with [`SampleQueue::split_static`].
+
+use core::cell::UnsafeCell;
+use core::mem::MaybeUninit;
+#[cfg(target_has_atomic = "8")]
+use core::sync::atomic::AtomicBool;
+use core::sync::atomic::{AtomicU32, AtomicUsize, Ordering};
+
+/// Fixed-size ring buffer holding up to `N - 1` records.
+///
+/// Split it into its [`Producer`], e.g. owned by an interrupt handler, and its [`Consumer`],
+/// owned by the publishing task. Records pushed while the queue is full are dropped and counted,
+/// see [`Consumer::dropped`].
+pub struct SampleQueue<T, const N: usize> {
+    buf: UnsafeCell<[MaybeUninit<T>; N]>,
+    /// Index of the next record to pop, only written by the consumer.
+    head: AtomicUsize,
+    /// Index of the next free slot, only written by the producer.
+    tail: AtomicUsize,
+    /// Number of records dropped because the queue was full, only written by the producer.
+    dropped: AtomicU32,
+    /// Whether [`SampleQueue::split_static`] handed out the producer and consumer.
+    #[cfg(target_has_atomic = "8")]
+    split: AtomicBool,
+}
+
+// The producer and consumer only access slots the other side has released through `head` and
+// `tail`.
+unsafe impl<T: Send, const N: usize> Sync for SampleQueue<T, N> {}
+
+impl<T: Copy, const N: usize> SampleQueue<T, N> {
+    pub const fn new() -> Self {
+        assert!(
+            N >= 2,
+            "The queue needs at least two slots to hold a record"
+        );
+        Self {
+            buf: UnsafeCell::new([const { MaybeUninit::uninit() }; N]),
+            head: AtomicUsize::new(0),
+            tail: AtomicUsize::new(0),
+            dropped: AtomicU32::new(0),
+            #[cfg(target_has_atomic = "8")]
+            split: AtomicBool::new(false),
+        }
+    }
+
+    pub fn split(&mut self) -> (Producer<'_, T, N>, Consumer<'_, T, N>) {
+        (Producer { queue: self }, Consumer { queue: self })
+    }
+
+    /// Split a queue living in a `static`, returning `None` if it was already split.
+    ///
+    /// This needs compare-and-swap. On targets without it, such as `thumbv6m`, get a
+    /// `&'static mut` to the queue, e.g. from a `StaticCell`, and use [`SampleQueue::split`].
+    #[cfg(target_has_atomic = "8")]
+    pub fn split_static(
+        &'static self,
+    ) -> Option<(Producer<'static, T, N>, Consumer<'static, T, N>)> {
+        if self.split.swap(true, Ordering::AcqRel) {
+            return None;
+        }
+        Some((Producer { queue: self }, Consumer { queue: self }))
+    }
+}
+
+impl<T: Copy, const N: usize> Default for SampleQueue<T, N> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+const fn next_index(index: usize, n: usize) -> usize {
+    if index + 1 == n { 0 } else { index + 1 }
+}
+
+/// The pushing side of a [`SampleQueue`].
+pub struct Producer<'a, T, const N: usize> {
+    queue: &'a SampleQueue<T, N>,
+}
+
+unsafe impl<T: Send, const N: usize> Send for Producer<'_, T, N> {}
+
+impl<T: Copy, const N: usize> Producer<'_, T, N> {
+    /// Push a record, returning `false` and counting it as dropped if the queue is full.
+    pub fn push(&mut self, record: T) -> bool {
+        let tail = self.queue.tail.load(Ordering::Relaxed);
+        let next = next_index(tail, N);
+        if next == self.queue.head.load(Ordering::Acquire) {
+            // Only the producer writes the counter, so no read-modify-write is needed.
+            let dropped = self.queue.dropped.load(Ordering::Relaxed);
+            self.queue
+                .dropped
+                .store(dropped.wrapping_add(1), Ordering::Relaxed);
+            return false;
+        }
+
+        // SAFETY: The slot at `tail` is not visible to the consumer until `tail` is advanced.
+        unsafe {
+            (*self.queue.buf.get())[tail].write(record);
+        }
+        self.queue.tail.store(next, Ordering::Release);
+        true
+    }
+}
+
+/// The popping side of a [`SampleQueue`].
+pub struct Consumer<'a, T, const N: usize> {
+    queue: &'a SampleQueue<T, N>,
+}
+
+unsafe impl<T: Send, const N: usize> Send for Consumer<'_, T, N> {}
+
+impl<T: Copy, const N: usize> Consumer<'_, T, N> {
+    pub fn pop(&mut self) -> Option<T> {
+        let head = self.queue.head.load(Ordering::Relaxed);
+        if head == self.queue.tail.load(Ordering::Acquire) {
+            return None;
+        }
+
+        // SAFETY: The producer initialized the slot before advancing `tail` past it, and doesn't
+        // reuse it until `head` is advanced.
+        let record = unsafe { (*self.queue.buf.get())[head].assume_init() };
+        self.queue
+            .head
+            .store(next_index(head, N), Ordering::Release);
+        Some(record)
+    }
+
+    pub fn len(&self) -> usize {
+        let head = self.queue.head.load(Ordering::Relaxed);
+        let tail = self.queue.tail.load(Ordering::Acquire);
+        (tail + N - head) % N
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Total number of records dropped because the queue was full, wrapping on overflow.
+    ///
+    /// Compare with a previous value to find the records dropped in between, e.g. to publish
+    /// them as part of health data.
+    pub fn dropped(&self) -> u32 {
+        self.queue.dropped.load(Ordering::Relaxed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_push_pop() {
+        let mut queue = SampleQueue::<u16, 4>::new();
+        let (mut producer, mut consumer) = queue.split();
+        assert_eq!(consumer.pop(), None);
+
+        assert!(producer.push(1));
+        assert!(producer.push(2));
+        assert_eq!(consumer.len(), 2);
+        assert_eq!(consumer.pop(), Some(1));
+
+        // Wrap around the end of the buffer.
+        assert!(producer.push(3));
+        assert!(producer.push(4));
+        assert_eq!(consumer.pop(), Some(2));
+        assert_eq!(consumer.pop(), Some(3));
+        assert_eq!(consumer.pop(), Some(4));
+        assert!(consumer.is_empty());
+    }
+
+    #[test]
+    fn test_overflow_accounting() {
+        let mut queue = SampleQueue::<u16, 3>::new();
+        let (mut producer, mut consumer) = queue.split();
+        assert!(producer.push(1));
+        assert!(producer.push(2));
+        assert!(!producer.push(3));
+        assert!(!producer.push(4));
+        assert_eq!(consumer.dropped(), 2);
+
+        assert_eq!(consumer.pop(), Some(1));
+        assert!(producer.push(5));
+        assert_eq!(consumer.pop(), Some(2));
+        assert_eq!(consumer.pop(), Some(5));
+        assert_eq!(consumer.dropped(), 2);
+    }
+
+    #[test]
+    fn test_split_static() {
+        static QUEUE: SampleQueue<u32, 8> = SampleQueue::new();
+
+        let (mut producer, mut consumer) = QUEUE.split_static().unwrap();
+        assert!(QUEUE.split_static().is_none());
+
+        // The producer is `'static`, so it can move to another thread like to an interrupt
+        // handler.
+        let handle = std::thread::spawn(move || {
+            for i in 0..100 {
+                while !producer.push(i) {
+                    std::thread::yield_now();
+                }
+            }
+        });
+
+        let mut expected = 0;
+        while expected < 100 {
+            match consumer.pop() {
+                Some(record) => {
+                    assert_eq!(record, expected);
+                    expected += 1;
+                }
+                None => std::thread::yield_now(),
+            }
+        }
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_concurrent_producer_and_consumer() {
+        let mut queue = SampleQueue::<u32, 8>::new();
+        let (mut producer, mut consumer) = queue.split();
+
+        std::thread::scope(|scope| {
+            scope.spawn(move || {
+                for i in 0..10_000 {
+                    while !producer.push(i) {
+                        std::thread::yield_now();
+                    }
+                }
+            });
+
+            let mut expected = 0;
+            while expected < 10_000 {
+                match consumer.pop() {
+                    Some(record) => {
+                        assert_eq!(record, expected);
+                        expected += 1;
+                    }
+                    None => std::thread::yield_now(),
+                }
+            }
+            // Retried pushes count as dropped, but every record arrived in order.
+            assert!(consumer.is_empty());
+        });
+    }
+}
@@ -0,0 +1,112 @@
+//! This module contains the storage interface for retained messages, so a broker can deliver
+//! them to new subscriptions.
+//!
+//! [`LastValueCache`] serves as RAM-backed store. Other implementations, e.g. backed by flash, can
+//! be plugged in by implementing [`RetainedStore`].
+
+use crate::cache::{CacheError, LastValueCache};
+use crate::topic;
+
+/// Storage for the most recent retained message of each topic.
+///
+/// Payloads are passed to closures rather than returned, so implementations can read them into
+/// a temporary buffer.
+pub trait RetainedStore {
+    type Error;
+
+    /// Store the retained message of the topic, replacing the previous one. As required by the
+    /// specification, an empty payload deletes the retained message instead.
+    fn set(&mut self, topic: &str, payload: &[u8]) -> Result<(), Self::Error>;
+
+    fn delete(&mut self, topic: &str) -> Result<(), Self::Error>;
+
+    /// Call `f` with the retained payload of the topic, if there is one.
+    fn get<R>(&mut self, topic: &str, f: impl FnOnce(&[u8]) -> R)
+    -> Result<Option<R>, Self::Error>;
+
+    /// Call `f` with the topic and payload of every retained message matching the filter.
+    ///
+    /// Retained messages are not sent for shared subscriptions (MQTT5 specification section
+    /// 4.8.2), so `f` must not be called for filters for which
+    /// [`topic::is_shared_subscription`] holds.
+    fn for_each_matching(
+        &mut self,
+        filter: &str,
+        f: impl FnMut(&str, &[u8]),
+    ) -> Result<(), Self::Error>;
+}
+
+impl<const N: usize, const TOPIC_LEN: usize, const PAYLOAD_LEN: usize> RetainedStore
+    for LastValueCache<N, TOPIC_LEN, PAYLOAD_LEN>
+{
+    type Error = CacheError;
+
+    fn set(&mut self, topic: &str, payload: &[u8]) -> Result<(), CacheError> {
+        self.update(topic, payload)
+    }
+
+    fn delete(&mut self, topic: &str) -> Result<(), CacheError> {
+        self.remove(topic);
+        Ok(())
+    }
+
+    fn get<R>(&mut self, topic: &str, f: impl FnOnce(&[u8]) -> R) -> Result<Option<R>, CacheError> {
+        Ok(LastValueCache::get(self, topic).map(f))
+    }
+
+    fn for_each_matching(
+        &mut self,
+        filter: &str,
+        mut f: impl FnMut(&str, &[u8]),
+    ) -> Result<(), CacheError> {
+        if topic::is_shared_subscription(filter) {
+            return Ok(());
+        }
+
+        self.iter()
+            .filter(|(topic, _)| topic::matches(filter, topic))
+            .for_each(|(topic, payload)| f(topic, payload));
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Retained messages delivered for a new subscription, in a generic broker-like context.
+    fn deliver<S: RetainedStore>(store: &mut S, filter: &str) -> Vec<(String, Vec<u8>)> {
+        let mut delivered = Vec::new();
+        let _ = store.for_each_matching(filter, |topic, payload| {
+            delivered.push((topic.to_string(), payload.to_vec()))
+        });
+        delivered
+    }
+
+    #[test]
+    fn test_cache_as_retained_store() {
+        let mut store = LastValueCache::<4, 32, 8>::new();
+        RetainedStore::set(&mut store, "home/kitchen/temp", b"21").unwrap();
+        RetainedStore::set(&mut store, "home/garage/door", b"open").unwrap();
+        RetainedStore::set(&mut store, "$SYS/uptime", b"100").unwrap();
+
+        assert_eq!(
+            RetainedStore::get(&mut store, "home/garage/door", |payload| payload.len()),
+            Ok(Some(4))
+        );
+        assert_eq!(
+            deliver(&mut store, "home/+/temp"),
+            [("home/kitchen/temp".to_string(), b"21".to_vec())]
+        );
+        assert_eq!(deliver(&mut store, "#").len(), 2);
+        assert_eq!(deliver(&mut store, "$share/group/home/#"), []);
+
+        RetainedStore::set(&mut store, "home/garage/door", b"").unwrap();
+        RetainedStore::delete(&mut store, "home/kitchen/temp").unwrap();
+        assert_eq!(deliver(&mut store, "home/#"), []);
+        assert_eq!(
+            RetainedStore::get(&mut store, "home/kitchen/temp", |_| ()),
+            Ok(None)
+        );
+    }
+}
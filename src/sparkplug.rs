@@ -0,0 +1,250 @@
+//! This module contains helpers for edge nodes following the Sparkplug B specification.
+//!
+//! An edge node session starts with a connection whose will message is the NDEATH, followed by
+//! publishing the NBIRTH. Both carry the same birth/death sequence number (`bdSeq`), which lets
+//! host applications match a death to the birth it ends. Every message the edge node publishes
+//! after that carries a sequence number (`seq`), starting at 0 with the NBIRTH.
+//!
+//! Sparkplug payloads are protobuf encoded. Apart from the NDEATH payload, which only contains
+//! the `bdSeq` metric, encoding payloads is left to the application.
+
+use crate::buf_writer::BufWriter;
+pub use crate::buf_writer::BufferTooSmall;
+
+/// The namespace all Sparkplug B topics start with.
+pub const NAMESPACE: &str = "spBv1.0";
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MessageType {
+    NBirth,
+    NDeath,
+    DBirth,
+    DDeath,
+    NData,
+    DData,
+    NCmd,
+    DCmd,
+}
+
+impl MessageType {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            MessageType::NBirth => "NBIRTH",
+            MessageType::NDeath => "NDEATH",
+            MessageType::DBirth => "DBIRTH",
+            MessageType::DDeath => "DDEATH",
+            MessageType::NData => "NDATA",
+            MessageType::DData => "DDATA",
+            MessageType::NCmd => "NCMD",
+            MessageType::DCmd => "DCMD",
+        }
+    }
+
+    /// Whether messages of this type concern a device attached to the edge node, rather than
+    /// the edge node itself.
+    pub fn is_device(&self) -> bool {
+        matches!(
+            self,
+            MessageType::DBirth | MessageType::DDeath | MessageType::DData | MessageType::DCmd
+        )
+    }
+}
+
+#[derive(Debug)]
+pub struct EdgeNode<'a> {
+    pub group_id: &'a str,
+    pub edge_node_id: &'a str,
+}
+
+impl EdgeNode<'_> {
+    /// The topic for the given message type, `spBv1.0/{group_id}/{type}/{edge_node_id}`, followed
+    /// by `/{device_id}` for device message types.
+    ///
+    /// Returns `None` if a device id is given for an edge node message type or vice versa, and
+    /// `Some(Err(_))` if the buffer is too small.
+    pub fn topic<'b>(
+        &self,
+        message_type: MessageType,
+        device_id: Option<&str>,
+        buf: &'b mut [u8],
+    ) -> Option<Result<&'b str, BufferTooSmall>> {
+        if message_type.is_device() != device_id.is_some() {
+            return None;
+        }
+
+        Some(self.write_topic(message_type.as_str(), device_id, buf))
+    }
+
+    fn write_topic<'b>(
+        &self,
+        message_type: &str,
+        device_id: Option<&str>,
+        buf: &'b mut [u8],
+    ) -> Result<&'b str, BufferTooSmall> {
+        let mut writer = BufWriter::new(buf);
+        for part in [NAMESPACE, self.group_id, message_type] {
+            writer.push_str(part)?;
+            writer.push_byte(b'/')?;
+        }
+        writer.push_str(self.edge_node_id)?;
+        if let Some(device_id) = device_id {
+            writer.push_byte(b'/')?;
+            writer.push_str(device_id)?;
+        }
+        Ok(writer.into_str())
+    }
+}
+
+/// Tracks the `bdSeq` and `seq` numbers of an edge node.
+#[derive(Debug)]
+pub struct Session {
+    next_bd_seq: u8,
+    bd_seq: u8,
+    seq: u8,
+}
+
+impl Session {
+    pub fn new() -> Self {
+        Self::with_bd_seq(0)
+    }
+
+    /// Start with the given `bdSeq`, e.g. the value following the last one used, restored from
+    /// non-volatile storage.
+    pub fn with_bd_seq(bd_seq: u8) -> Self {
+        Self {
+            next_bd_seq: bd_seq,
+            bd_seq,
+            seq: 0,
+        }
+    }
+
+    /// Start a new session before connecting, returning the `bdSeq` to put into the NDEATH will
+    /// message and the NBIRTH.
+    ///
+    /// Resets the sequence number, so the next call to [`Session::next_seq`] returns 0 for the
+    /// NBIRTH.
+    pub fn connect(&mut self) -> u8 {
+        self.bd_seq = self.next_bd_seq;
+        self.next_bd_seq = self.next_bd_seq.wrapping_add(1);
+        self.seq = 0;
+        self.bd_seq
+    }
+
+    /// The `bdSeq` of the current session.
+    pub fn bd_seq(&self) -> u8 {
+        self.bd_seq
+    }
+
+    /// The sequence number for the next message published, wrapping from 255 to 0.
+    pub fn next_seq(&mut self) -> u8 {
+        let seq = self.seq;
+        self.seq = self.seq.wrapping_add(1);
+        seq
+    }
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Encode the NDEATH payload, which contains the `bdSeq` metric only.
+pub fn death_payload(bd_seq: u8, buf: &mut [u8]) -> Result<&[u8], BufferTooSmall> {
+    // Protobuf field keys, `(field number << 3) | wire type`.
+    const PAYLOAD_METRICS: u8 = (2 << 3) | 2;
+    const METRIC_NAME: u8 = (1 << 3) | 2;
+    const METRIC_DATATYPE: u8 = 4 << 3;
+    const METRIC_LONG_VALUE: u8 = 11 << 3;
+    const DATATYPE_INT64: u8 = 4;
+    const NAME: &str = "bdSeq";
+
+    let value_len = if bd_seq < 0x80 { 1 } else { 2 };
+    let metric_len = 2 + NAME.len() + 2 + 1 + value_len;
+
+    let mut writer = BufWriter::new(buf);
+    writer.push_bytes(&[PAYLOAD_METRICS, metric_len as u8])?;
+    writer.push_bytes(&[METRIC_NAME, NAME.len() as u8])?;
+    writer.push_str(NAME)?;
+    writer.push_bytes(&[METRIC_DATATYPE, DATATYPE_INT64, METRIC_LONG_VALUE])?;
+    if bd_seq < 0x80 {
+        writer.push_byte(bd_seq)?;
+    } else {
+        writer.push_bytes(&[bd_seq | 0x80, 0x01])?;
+    }
+    Ok(writer.into_bytes())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const NODE: EdgeNode = EdgeNode {
+        group_id: "plant1",
+        edge_node_id: "gateway",
+    };
+
+    #[test]
+    fn test_topic() {
+        let mut buf = [0u8; 64];
+        assert_eq!(
+            NODE.topic(MessageType::NBirth, None, &mut buf),
+            Some(Ok("spBv1.0/plant1/NBIRTH/gateway"))
+        );
+        assert_eq!(
+            NODE.topic(MessageType::DData, Some("pump"), &mut buf),
+            Some(Ok("spBv1.0/plant1/DDATA/gateway/pump"))
+        );
+        assert_eq!(NODE.topic(MessageType::NData, Some("pump"), &mut buf), None);
+        assert_eq!(NODE.topic(MessageType::DCmd, None, &mut buf), None);
+
+        let mut buf = [0u8; 8];
+        assert_eq!(
+            NODE.topic(MessageType::NDeath, None, &mut buf),
+            Some(Err(BufferTooSmall))
+        );
+    }
+
+    #[test]
+    fn test_session_sequence_numbers() {
+        let mut session = Session::new();
+        assert_eq!(session.connect(), 0);
+        assert_eq!(session.next_seq(), 0);
+        assert_eq!(session.next_seq(), 1);
+
+        assert_eq!(session.connect(), 1);
+        assert_eq!(session.bd_seq(), 1);
+        assert_eq!(session.next_seq(), 0);
+        for _ in 1..256 {
+            session.next_seq();
+        }
+        assert_eq!(session.next_seq(), 0);
+    }
+
+    #[test]
+    fn test_session_bd_seq_wraps() {
+        let mut session = Session::with_bd_seq(255);
+        assert_eq!(session.connect(), 255);
+        assert_eq!(session.connect(), 0);
+    }
+
+    #[test]
+    fn test_death_payload() {
+        let mut buf = [0u8; 16];
+        assert_eq!(
+            death_payload(5, &mut buf),
+            Ok(&[
+                0x12, 0x0B, 0x0A, 0x05, b'b', b'd', b'S', b'e', b'q', 0x20, 0x04, 0x58, 0x05
+            ][..])
+        );
+        assert_eq!(
+            death_payload(200, &mut buf),
+            Ok(&[
+                0x12, 0x0C, 0x0A, 0x05, b'b', b'd', b'S', b'e', b'q', 0x20, 0x04, 0x58, 0xC8, 0x01
+            ][..])
+        );
+
+        let mut buf = [0u8; 8];
+        assert_eq!(death_payload(5, &mut buf), Err(BufferTooSmall));
+    }
+}
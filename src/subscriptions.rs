@@ -0,0 +1,246 @@
+//! This module contains a statically sized table of subscriptions, for finding the subscribers of
+//! a topic on the broker side or the handlers of a received message on the client side.
+
+use crate::packet::subscribe::SubscriptionOptions;
+use crate::topic::{self, TopicError};
+
+/// Reasons for a subscription not being added to the table.
+#[derive(Debug, PartialEq, Eq)]
+pub enum SubscriptionError {
+    InvalidFilter(TopicError),
+    /// The filter is longer than the `FILTER_LEN` of the table.
+    FilterTooLong,
+    /// All `N` entries are in use.
+    Full,
+}
+
+#[derive(Debug)]
+struct Entry<C, const FILTER_LEN: usize> {
+    subscriber: C,
+    filter: [u8; FILTER_LEN],
+    filter_len: usize,
+    options: SubscriptionOptions,
+}
+
+impl<C, const FILTER_LEN: usize> Entry<C, FILTER_LEN> {
+    fn filter(&self) -> &str {
+        core::str::from_utf8(&self.filter[..self.filter_len]).expect("Filters are stored from str")
+    }
+}
+
+/// Table of up to `N` subscriptions with filters of up to `FILTER_LEN` bytes.
+///
+/// Subscribers are identified by `C`, e.g. a client index on the broker side or a handler id on
+/// the client side. Each subscriber has at most one subscription per filter.
+///
+/// The table is scanned linearly, so matching a topic costs `N` filter comparisons, each
+/// proportional to the number of topic levels. This keeps the table compact and is fast for the
+/// tens of subscriptions typical on embedded devices. See `benches/subscriptions.rs` for
+/// measurements.
+#[derive(Debug)]
+pub struct SubscriptionTable<C, const N: usize, const FILTER_LEN: usize> {
+    entries: [Option<Entry<C, FILTER_LEN>>; N],
+}
+
+impl<C: Copy + PartialEq, const N: usize, const FILTER_LEN: usize>
+    SubscriptionTable<C, N, FILTER_LEN>
+{
+    pub const fn new() -> Self {
+        Self {
+            entries: [const { None }; N],
+        }
+    }
+
+    /// Add a subscription, replacing the options if the subscriber is already subscribed to the
+    /// filter.
+    pub fn subscribe(
+        &mut self,
+        subscriber: C,
+        filter: &str,
+        options: SubscriptionOptions,
+    ) -> Result<(), SubscriptionError> {
+        topic::validate_topic_filter(filter).map_err(SubscriptionError::InvalidFilter)?;
+        if filter.len() > FILTER_LEN {
+            return Err(SubscriptionError::FilterTooLong);
+        }
+
+        if let Some(entry) = self.find_mut(subscriber, filter) {
+            entry.options = options;
+            return Ok(());
+        }
+
+        let slot = self
+            .entries
+            .iter_mut()
+            .find(|entry| entry.is_none())
+            .ok_or(SubscriptionError::Full)?;
+        let mut entry = Entry {
+            subscriber,
+            filter: [0; FILTER_LEN],
+            filter_len: filter.len(),
+            options,
+        };
+        entry.filter[..filter.len()].copy_from_slice(filter.as_bytes());
+        *slot = Some(entry);
+        Ok(())
+    }
+
+    /// Remove a subscription, returning whether the subscriber was subscribed to the filter.
+    pub fn unsubscribe(&mut self, subscriber: C, filter: &str) -> bool {
+        for slot in &mut self.entries {
+            if slot
+                .as_ref()
+                .is_some_and(|entry| entry.subscriber == subscriber && entry.filter() == filter)
+            {
+                *slot = None;
+                return true;
+            }
+        }
+        false
+    }
+
+    /// Remove all subscriptions of the subscriber, e.g. when its session ends.
+    pub fn remove_subscriber(&mut self, subscriber: C) {
+        for slot in &mut self.entries {
+            if slot
+                .as_ref()
+                .is_some_and(|entry| entry.subscriber == subscriber)
+            {
+                *slot = None;
+            }
+        }
+    }
+
+    /// Iterate over the subscriptions whose filter matches the topic name, yielding the
+    /// subscriber, filter and options of each.
+    ///
+    /// A subscriber with several matching filters is yielded once per filter.
+    pub fn matching<'a>(
+        &'a self,
+        topic: &'a str,
+    ) -> impl Iterator<Item = (C, &'a str, SubscriptionOptions)> + 'a {
+        self.iter()
+            .filter(move |(_, filter, _)| topic::matches(filter, topic))
+    }
+
+    /// Iterate over all subscriptions.
+    pub fn iter(&self) -> impl Iterator<Item = (C, &str, SubscriptionOptions)> {
+        self.entries
+            .iter()
+            .flatten()
+            .map(|entry| (entry.subscriber, entry.filter(), entry.options))
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.iter().flatten().count()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    fn find_mut(&mut self, subscriber: C, filter: &str) -> Option<&mut Entry<C, FILTER_LEN>> {
+        self.entries
+            .iter_mut()
+            .flatten()
+            .find(|entry| entry.subscriber == subscriber && entry.filter() == filter)
+    }
+}
+
+impl<C: Copy + PartialEq, const N: usize, const FILTER_LEN: usize> Default
+    for SubscriptionTable<C, N, FILTER_LEN>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::{qos::QoS, subscribe::RetainHandling};
+
+    const QOS0: SubscriptionOptions = SubscriptionOptions {
+        qos: QoS::AtMostOnce,
+        no_local: false,
+        retain_as_published: false,
+        retain_handling: RetainHandling::SendOnSubscribe,
+    };
+
+    fn subscribers(table: &SubscriptionTable<u8, 8, 32>, topic: &str) -> Vec<u8> {
+        table.matching(topic).map(|(client, _, _)| client).collect()
+    }
+
+    #[test]
+    fn test_matching() {
+        let mut table = SubscriptionTable::<u8, 8, 32>::new();
+        table.subscribe(1, "sensors/+/temperature", QOS0).unwrap();
+        table.subscribe(2, "sensors/#", QOS0).unwrap();
+        table.subscribe(3, "actuators/#", QOS0).unwrap();
+        table
+            .subscribe(4, "$share/workers/sensors/#", QOS0)
+            .unwrap();
+
+        assert_eq!(subscribers(&table, "sensors/a/temperature"), [1, 2, 4]);
+        assert_eq!(subscribers(&table, "sensors/a/humidity"), [2, 4]);
+        assert_eq!(subscribers(&table, "actuators/pump"), [3]);
+        assert_eq!(subscribers(&table, "$SYS/uptime"), []);
+    }
+
+    #[test]
+    fn test_const_new() {
+        static TABLE: SubscriptionTable<u8, 4, 16> = SubscriptionTable::new();
+        assert!(TABLE.is_empty());
+    }
+
+    #[test]
+    fn test_subscribe_replaces_options() {
+        let mut table = SubscriptionTable::<u8, 8, 32>::new();
+        table.subscribe(1, "a/#", QOS0).unwrap();
+        table
+            .subscribe(1, "a/#", SubscriptionOptions::new(QoS::AtLeastOnce))
+            .unwrap();
+        // Another subscriber with the same filter is a separate subscription.
+        table.subscribe(2, "a/#", QOS0).unwrap();
+
+        assert_eq!(table.len(), 2);
+        let (_, filter, options) = table.matching("a/b").next().unwrap();
+        assert_eq!(filter, "a/#");
+        assert_eq!(options.qos, QoS::AtLeastOnce);
+    }
+
+    #[test]
+    fn test_unsubscribe_and_remove_subscriber() {
+        let mut table = SubscriptionTable::<u8, 8, 32>::new();
+        table.subscribe(1, "a", QOS0).unwrap();
+        table.subscribe(1, "b", QOS0).unwrap();
+        table.subscribe(2, "a", QOS0).unwrap();
+
+        assert!(table.unsubscribe(2, "a"));
+        assert!(!table.unsubscribe(2, "a"));
+        assert_eq!(subscribers(&table, "a"), [1]);
+
+        table.remove_subscriber(1);
+        assert!(table.is_empty());
+    }
+
+    #[test]
+    fn test_subscribe_errors() {
+        let mut table = SubscriptionTable::<u8, 1, 4>::new();
+        assert_eq!(
+            table.subscribe(1, "a/#/b", QOS0),
+            Err(SubscriptionError::InvalidFilter(
+                TopicError::MisplacedMultiLevelWildcard
+            ))
+        );
+        assert_eq!(
+            table.subscribe(1, "a/b/c", QOS0),
+            Err(SubscriptionError::FilterTooLong)
+        );
+        table.subscribe(1, "a/b", QOS0).unwrap();
+        assert_eq!(
+            table.subscribe(2, "a/b", QOS0),
+            Err(SubscriptionError::Full)
+        );
+    }
+}
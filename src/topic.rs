@@ -0,0 +1,304 @@
+//! This module contains utilities for working with MQTT topic names and topic filters.
+
+const LEVEL_SEPARATOR: char = '/';
+const SINGLE_LEVEL_WILDCARD: &str = "+";
+const MULTI_LEVEL_WILDCARD: &str = "#";
+const SHARED_SUBSCRIPTION_PREFIX: &str = "$share/";
+
+/// Topic names and filters are UTF-8 encoded strings, which are limited to this many bytes.
+const MAX_TOPIC_LENGTH: usize = u16::MAX as usize;
+
+/// Reasons for a topic name or topic filter being invalid.
+#[derive(Debug, PartialEq, Eq)]
+pub enum TopicError {
+    /// The topic name or filter is empty.
+    Empty,
+    /// The topic name or filter is longer than 65535 bytes.
+    TooLong,
+    /// The topic name or filter contains the null character U+0000.
+    ContainsNul,
+    /// A topic name contains a `+` or `#` wildcard character.
+    WildcardInTopicName,
+    /// A `#` wildcard in a topic filter is not the entire last level.
+    MisplacedMultiLevelWildcard,
+    /// A `+` wildcard in a topic filter does not occupy an entire level.
+    MisplacedSingleLevelWildcard,
+    /// The share name of a shared subscription is empty or contains a wildcard character.
+    InvalidShareName,
+    /// A shared subscription is missing the topic filter after the share name.
+    MissingSharedFilter,
+}
+
+/// Check that the given string is a valid topic name, as used in PUBLISH packets.
+pub fn validate_topic_name(topic: &str) -> Result<(), TopicError> {
+    validate_common(topic)?;
+
+    if topic.contains(['+', '#']) {
+        return Err(TopicError::WildcardInTopicName);
+    }
+
+    Ok(())
+}
+
+/// Check that the given string is a valid topic filter, as used in SUBSCRIBE and UNSUBSCRIBE
+/// packets.
+///
+/// Shared subscription filters (`$share/{ShareName}/{filter}`) are accepted.
+pub fn validate_topic_filter(filter: &str) -> Result<(), TopicError> {
+    validate_common(filter)?;
+
+    let filter = match filter.strip_prefix(SHARED_SUBSCRIPTION_PREFIX) {
+        Some(shared) => {
+            let (share_name, filter) = shared
+                .split_once(LEVEL_SEPARATOR)
+                .ok_or(TopicError::MissingSharedFilter)?;
+            if share_name.is_empty() || share_name.contains(['+', '#']) {
+                return Err(TopicError::InvalidShareName);
+            }
+            if filter.is_empty() {
+                return Err(TopicError::MissingSharedFilter);
+            }
+            filter
+        }
+        None => filter,
+    };
+
+    let mut levels = filter.split(LEVEL_SEPARATOR).peekable();
+    while let Some(level) = levels.next() {
+        match level {
+            MULTI_LEVEL_WILDCARD if levels.peek().is_some() => {
+                return Err(TopicError::MisplacedMultiLevelWildcard);
+            }
+            MULTI_LEVEL_WILDCARD | SINGLE_LEVEL_WILDCARD => {}
+            _ if level.contains('#') => return Err(TopicError::MisplacedMultiLevelWildcard),
+            _ if level.contains('+') => return Err(TopicError::MisplacedSingleLevelWildcard),
+            _ => {}
+        }
+    }
+
+    Ok(())
+}
+
+/// Checks shared between topic names and topic filters.
+fn validate_common(s: &str) -> Result<(), TopicError> {
+    if s.is_empty() {
+        return Err(TopicError::Empty);
+    }
+    if s.len() > MAX_TOPIC_LENGTH {
+        return Err(TopicError::TooLong);
+    }
+    if s.contains('\0') {
+        return Err(TopicError::ContainsNul);
+    }
+
+    Ok(())
+}
+
+/// Check whether the given topic name matches the given topic filter.
+///
+/// Shared subscription filters (`$share/{ShareName}/{filter}`) are matched using the filter
+/// following the share name. Following MQTT5 specification section 4.7.2, topic names starting
+/// with `$` are not matched by filters starting with a wildcard.
+pub fn matches(filter: &str, topic: &str) -> bool {
+    let filter = match filter.strip_prefix(SHARED_SUBSCRIPTION_PREFIX) {
+        Some(shared) => match shared.split_once(LEVEL_SEPARATOR) {
+            Some((_share_name, filter)) => filter,
+            // A shared subscription without a filter can never match.
+            None => return false,
+        },
+        None => filter,
+    };
+
+    if topic.starts_with('$')
+        && (filter.starts_with(SINGLE_LEVEL_WILDCARD) || filter.starts_with(MULTI_LEVEL_WILDCARD))
+    {
+        return false;
+    }
+
+    let mut filter_levels = filter.split(LEVEL_SEPARATOR);
+    let mut topic_levels = topic.split(LEVEL_SEPARATOR);
+
+    loop {
+        match (filter_levels.next(), topic_levels.next()) {
+            // "#" also matches the parent level, so it matches whether or not levels remain.
+            (Some(MULTI_LEVEL_WILDCARD), _) => return true,
+            (Some(SINGLE_LEVEL_WILDCARD), Some(_)) => {}
+            (Some(filter_level), Some(topic_level)) if filter_level == topic_level => {}
+            (None, None) => return true,
+            _ => return false,
+        }
+    }
+}
+
+/// Check whether the given topic filter is a shared subscription filter
+/// (`$share/{ShareName}/{filter}`).
+pub fn is_shared_subscription(filter: &str) -> bool {
+    filter.starts_with(SHARED_SUBSCRIPTION_PREFIX)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_matches_exact() {
+        assert!(matches("sport/tennis/player1", "sport/tennis/player1"));
+        assert!(!matches("sport/tennis/player1", "sport/tennis/player2"));
+        assert!(!matches("sport/tennis", "sport/tennis/player1"));
+        assert!(!matches("sport/tennis/player1", "sport/tennis"));
+    }
+
+    #[test]
+    fn test_matches_multi_level_wildcard() {
+        assert!(matches("sport/tennis/player1/#", "sport/tennis/player1"));
+        assert!(matches(
+            "sport/tennis/player1/#",
+            "sport/tennis/player1/ranking"
+        ));
+        assert!(matches(
+            "sport/tennis/player1/#",
+            "sport/tennis/player1/score/wimbledon"
+        ));
+        assert!(matches("sport/#", "sport"));
+        assert!(matches("#", "sport/tennis"));
+        assert!(matches("#", "/"));
+        assert!(!matches("sport/tennis/#", "sport/football"));
+    }
+
+    #[test]
+    fn test_matches_single_level_wildcard() {
+        assert!(matches("sport/tennis/+", "sport/tennis/player1"));
+        assert!(!matches("sport/tennis/+", "sport/tennis/player1/ranking"));
+        assert!(!matches("sport/+", "sport"));
+        assert!(matches("sport/+", "sport/"));
+        assert!(matches("+", "sport"));
+        assert!(!matches("+", "/finance"));
+        assert!(matches("+/+", "/finance"));
+        assert!(matches("/+", "/finance"));
+        assert!(matches("+/tennis/#", "sport/tennis/player1"));
+    }
+
+    #[test]
+    fn test_matches_empty_levels() {
+        assert!(matches("/", "/"));
+        assert!(matches("a//b", "a//b"));
+        assert!(matches("a/+/b", "a//b"));
+        assert!(!matches("a/b", "a/b/"));
+    }
+
+    #[test]
+    fn test_matches_dollar_topics() {
+        assert!(!matches("#", "$SYS/broker/uptime"));
+        assert!(!matches("+/monitor/Clients", "$SYS/monitor/Clients"));
+        assert!(matches("$SYS/#", "$SYS/monitor/Clients"));
+        assert!(matches("$SYS/monitor/+", "$SYS/monitor/Clients"));
+    }
+
+    #[test]
+    fn test_matches_shared_subscription() {
+        assert!(matches("$share/group/sport/#", "sport/tennis"));
+        assert!(matches("$share/group/+/tennis", "sport/tennis"));
+        assert!(!matches("$share/group/sport/#", "finance"));
+        assert!(!matches("$share/group/#", "$SYS/broker/uptime"));
+        assert!(!matches("$share/group", "group"));
+    }
+
+    #[test]
+    fn test_is_shared_subscription() {
+        assert!(is_shared_subscription("$share/group/sport/#"));
+        assert!(!is_shared_subscription("sport/#"));
+        assert!(!is_shared_subscription("$SYS/#"));
+    }
+
+    #[test]
+    fn test_validate_topic_name_valid() {
+        assert_eq!(validate_topic_name("sport/tennis/player1"), Ok(()));
+        assert_eq!(validate_topic_name("/"), Ok(()));
+        assert_eq!(validate_topic_name("$SYS/broker"), Ok(()));
+        assert_eq!(validate_topic_name(" "), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_topic_name_invalid() {
+        assert_eq!(validate_topic_name(""), Err(TopicError::Empty));
+        assert_eq!(validate_topic_name("a\0b"), Err(TopicError::ContainsNul));
+        assert_eq!(
+            validate_topic_name("sport/+"),
+            Err(TopicError::WildcardInTopicName)
+        );
+        assert_eq!(
+            validate_topic_name("sport/#"),
+            Err(TopicError::WildcardInTopicName)
+        );
+        assert_eq!(
+            validate_topic_name("sport/tennis#"),
+            Err(TopicError::WildcardInTopicName)
+        );
+    }
+
+    #[test]
+    fn test_validate_topic_name_length() {
+        let max = "a".repeat(MAX_TOPIC_LENGTH);
+        assert_eq!(validate_topic_name(&max), Ok(()));
+        let too_long = "a".repeat(MAX_TOPIC_LENGTH + 1);
+        assert_eq!(validate_topic_name(&too_long), Err(TopicError::TooLong));
+    }
+
+    #[test]
+    fn test_validate_topic_filter_valid() {
+        assert_eq!(validate_topic_filter("sport/tennis/player1"), Ok(()));
+        assert_eq!(validate_topic_filter("#"), Ok(()));
+        assert_eq!(validate_topic_filter("sport/#"), Ok(()));
+        assert_eq!(validate_topic_filter("+"), Ok(()));
+        assert_eq!(validate_topic_filter("+/tennis/#"), Ok(()));
+        assert_eq!(validate_topic_filter("sport/+/player1"), Ok(()));
+        assert_eq!(validate_topic_filter("/+"), Ok(()));
+        assert_eq!(validate_topic_filter("$share/group/sport/#"), Ok(()));
+    }
+
+    #[test]
+    fn test_validate_topic_filter_invalid() {
+        assert_eq!(validate_topic_filter(""), Err(TopicError::Empty));
+        assert_eq!(validate_topic_filter("a\0/#"), Err(TopicError::ContainsNul));
+        assert_eq!(
+            validate_topic_filter("sport/tennis#"),
+            Err(TopicError::MisplacedMultiLevelWildcard)
+        );
+        assert_eq!(
+            validate_topic_filter("sport/#/ranking"),
+            Err(TopicError::MisplacedMultiLevelWildcard)
+        );
+        assert_eq!(
+            validate_topic_filter("sport+"),
+            Err(TopicError::MisplacedSingleLevelWildcard)
+        );
+        assert_eq!(
+            validate_topic_filter("sport/+tennis"),
+            Err(TopicError::MisplacedSingleLevelWildcard)
+        );
+    }
+
+    #[test]
+    fn test_validate_topic_filter_shared_subscription() {
+        assert_eq!(
+            validate_topic_filter("$share/group"),
+            Err(TopicError::MissingSharedFilter)
+        );
+        assert_eq!(
+            validate_topic_filter("$share/group/"),
+            Err(TopicError::MissingSharedFilter)
+        );
+        assert_eq!(
+            validate_topic_filter("$share//sport"),
+            Err(TopicError::InvalidShareName)
+        );
+        assert_eq!(
+            validate_topic_filter("$share/gr+oup/sport"),
+            Err(TopicError::InvalidShareName)
+        );
+        assert_eq!(
+            validate_topic_filter("$share/group/sport/#/x"),
+            Err(TopicError::MisplacedMultiLevelWildcard)
+        );
+    }
+}
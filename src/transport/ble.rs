@@ -0,0 +1,150 @@
+//! This module contains an adapter for carrying MQTT over MTU-limited BLE links, such as the
+//! Nordic UART Service or an L2CAP channel.
+//!
+//! MQTT packets carry their own length, so received fragments need no extra framing and are
+//! simply read in sequence. Outgoing bytes are collected and sent in fragments of at most `MTU`
+//! bytes, rather than issuing one characteristic write for each small write of the packet
+//! encoder.
+
+use embedded_io_async::{ErrorType, Read, Write};
+
+/// Transport that sends data written to it in fragments of up to `MTU` bytes.
+///
+/// Every write to the inner transport is a single fragment, e.g. one characteristic write or
+/// notification, so the inner transport must accept up to `MTU` bytes at once. Buffered bytes are
+/// sent when a fragment is full and on [`flush`](Write::flush).
+#[derive(Debug)]
+pub struct NusTransport<T, const MTU: usize> {
+    inner: T,
+    tx: [u8; MTU],
+    tx_len: usize,
+}
+
+impl<T, const MTU: usize> NusTransport<T, MTU> {
+    pub fn new(inner: T) -> Self {
+        const { assert!(MTU > 0, "The MTU must allow at least one byte per fragment") };
+        Self {
+            inner,
+            tx: [0; MTU],
+            tx_len: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: Write, const MTU: usize> NusTransport<T, MTU> {
+    async fn send_fragment(&mut self) -> Result<(), T::Error> {
+        if self.tx_len > 0 {
+            self.inner.write_all(&self.tx[..self.tx_len]).await?;
+            self.tx_len = 0;
+        }
+        Ok(())
+    }
+}
+
+impl<T: ErrorType, const MTU: usize> ErrorType for NusTransport<T, MTU> {
+    type Error = T::Error;
+}
+
+impl<T: Read, const MTU: usize> Read for NusTransport<T, MTU> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        self.inner.read(buf).await
+    }
+}
+
+impl<T: Write, const MTU: usize> Write for NusTransport<T, MTU> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        if self.tx_len == MTU {
+            self.send_fragment().await?;
+        }
+
+        let len = buf.len().min(MTU - self.tx_len);
+        self.tx[self.tx_len..self.tx_len + len].copy_from_slice(&buf[..len]);
+        self.tx_len += len;
+        Ok(len)
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        self.send_fragment().await?;
+        self.inner.flush().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        packet::data_representation,
+        transport::mock::{MockTransport, Step},
+    };
+
+    /// Records the length of each write, i.e. each fragment sent.
+    struct Fragments {
+        data: [u8; 64],
+        lens: [usize; 8],
+        count: usize,
+    }
+
+    impl ErrorType for Fragments {
+        type Error = core::convert::Infallible;
+    }
+
+    impl Write for Fragments {
+        async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+            let offset: usize = self.lens[..self.count].iter().sum();
+            self.data[offset..offset + buf.len()].copy_from_slice(buf);
+            self.lens[self.count] = buf.len();
+            self.count += 1;
+            Ok(buf.len())
+        }
+    }
+
+    #[tokio::test]
+    async fn test_write_fragments() {
+        let fragments = Fragments {
+            data: [0; 64],
+            lens: [0; 8],
+            count: 0,
+        };
+        let mut transport = NusTransport::<_, 4>::new(fragments);
+
+        // Many small writes, like the packet encoder issues.
+        for byte in 0..10 {
+            data_representation::write_u8(byte, &mut transport)
+                .await
+                .unwrap();
+        }
+        assert_eq!(transport.inner.count, 2);
+
+        transport.flush().await.unwrap();
+        let fragments = transport.into_inner();
+        assert_eq!(fragments.lens[..fragments.count], [4, 4, 2]);
+        assert_eq!(fragments.data[..10], [0, 1, 2, 3, 4, 5, 6, 7, 8, 9]);
+    }
+
+    #[tokio::test]
+    async fn test_flush_without_data() {
+        let fragments = Fragments {
+            data: [0; 64],
+            lens: [0; 8],
+            count: 0,
+        };
+        let mut transport = NusTransport::<_, 4>::new(fragments);
+        transport.flush().await.unwrap();
+        assert_eq!(transport.into_inner().count, 0);
+    }
+
+    #[tokio::test]
+    async fn test_read_across_fragments() {
+        // A variable byte integer split over two received fragments reads as one.
+        let script = [Step::Data(&[0x80]), Step::Data(&[0x01])];
+        let mut transport = NusTransport::<_, 1>::new(MockTransport::<0>::with_script(&script));
+        let value = data_representation::read_variable_byte_integer(&mut transport)
+            .await
+            .unwrap();
+        assert_eq!(value, 128);
+    }
+}
@@ -0,0 +1,370 @@
+//! This module contains a framing layer for carrying MQTT packets over a raw byte pipe, such as
+//! a UART, RS-485 bus or USB CDC link, where there is no IP stack to delimit and protect data.
+//!
+//! Each frame consists of the payload followed by its CRC-16/CCITT-FALSE in big endian order,
+//! COBS encoded and terminated by a zero byte. Receivers can therefore resynchronize on the next
+//! zero byte after line noise or a partially received frame.
+
+use embedded_io_async::{ErrorKind, ErrorType, Read, Write};
+
+const DELIMITER: u8 = 0x00;
+/// Largest COBS block, consisting of the code byte and 254 non-zero data bytes.
+const MAX_BLOCK_CODE: u8 = 0xFF;
+const CRC_LEN: usize = 2;
+
+#[derive(Debug)]
+pub enum FramingError<E> {
+    /// A frame does not fit into the frame buffer.
+    FrameTooLong,
+    /// A received frame is not valid COBS, or failed the CRC check.
+    Corrupted,
+    /// The underlying transport was closed in the middle of a frame.
+    UnexpectedEof,
+    Transport(E),
+}
+
+impl<E: embedded_io_async::Error> embedded_io_async::Error for FramingError<E> {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            FramingError::FrameTooLong => ErrorKind::OutOfMemory,
+            FramingError::Corrupted | FramingError::UnexpectedEof => ErrorKind::InvalidData,
+            FramingError::Transport(e) => e.kind(),
+        }
+    }
+}
+
+/// Transport that frames data written to it and unframes data read from it.
+///
+/// All bytes written between two calls to [`flush`](Write::flush) are sent as one frame, so the
+/// caller should flush after each complete packet. Reads return the payload of received frames.
+///
+/// `N` is the size of each of the transmit and receive buffers. It must hold the largest
+/// payload plus the two CRC bytes.
+#[derive(Debug)]
+pub struct CobsTransport<T, const N: usize> {
+    inner: T,
+    tx: [u8; N],
+    tx_len: usize,
+    rx: [u8; N],
+    rx_len: usize,
+    rx_pos: usize,
+}
+
+impl<T, const N: usize> CobsTransport<T, N> {
+    pub fn new(inner: T) -> Self {
+        const {
+            assert!(
+                N > CRC_LEN,
+                "The buffers need room for the CRC and at least one byte of payload"
+            )
+        };
+        Self {
+            inner,
+            tx: [0; N],
+            tx_len: 0,
+            rx: [0; N],
+            rx_len: 0,
+            rx_pos: 0,
+        }
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+}
+
+impl<T: ErrorType, const N: usize> ErrorType for CobsTransport<T, N> {
+    type Error = FramingError<T::Error>;
+}
+
+impl<T: Read, const N: usize> CobsTransport<T, N> {
+    /// Receive the next frame with a valid CRC into the receive buffer.
+    ///
+    /// Returns `false` if the transport was closed before a new frame started.
+    async fn receive_frame(&mut self) -> Result<bool, FramingError<T::Error>> {
+        loop {
+            let mut len = 0;
+            let mut overflow = false;
+            let mut block_code = MAX_BLOCK_CODE;
+            let mut block_left = 0u8;
+            let mut started = false;
+
+            loop {
+                let mut byte = [0u8; 1];
+                match self.inner.read(&mut byte).await {
+                    Ok(0) if !started => return Ok(false),
+                    Ok(0) => return Err(FramingError::UnexpectedEof),
+                    Ok(_) => {}
+                    Err(e) => return Err(FramingError::Transport(e)),
+                }
+                let byte = byte[0];
+                if byte == DELIMITER {
+                    break;
+                }
+
+                let decoded = match block_left {
+                    0 => {
+                        // Start of a new block. Every block except the first one and those
+                        // following a maximum size block is preceded by an encoded zero.
+                        let zero = started && block_code != MAX_BLOCK_CODE;
+                        block_code = byte;
+                        block_left = byte - 1;
+                        zero.then_some(0)
+                    }
+                    _ => {
+                        block_left -= 1;
+                        Some(byte)
+                    }
+                };
+                started = true;
+
+                if let Some(decoded) = decoded {
+                    match self.rx.get_mut(len) {
+                        Some(slot) => *slot = decoded,
+                        // Keep consuming until the delimiter, so the next frame is read from
+                        // its start.
+                        None => overflow = true,
+                    }
+                    len += 1;
+                }
+            }
+
+            if !started {
+                // Empty frame, e.g. a delimiter sent to flush out line noise.
+                continue;
+            }
+            if overflow {
+                return Err(FramingError::FrameTooLong);
+            }
+            if block_left != 0 || len < CRC_LEN {
+                return Err(FramingError::Corrupted);
+            }
+
+            let (payload, crc) = self.rx[..len].split_at(len - CRC_LEN);
+            if crc16(payload).to_be_bytes() != crc {
+                return Err(FramingError::Corrupted);
+            }
+
+            self.rx_len = payload.len();
+            self.rx_pos = 0;
+            return Ok(true);
+        }
+    }
+}
+
+impl<T: Read, const N: usize> Read for CobsTransport<T, N> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, Self::Error> {
+        // Frames with an empty payload don't carry data, skip over them.
+        while self.rx_pos == self.rx_len {
+            if !self.receive_frame().await? {
+                return Ok(0);
+            }
+        }
+
+        let available = &self.rx[self.rx_pos..self.rx_len];
+        let len = available.len().min(buf.len());
+        buf[..len].copy_from_slice(&available[..len]);
+        self.rx_pos += len;
+        Ok(len)
+    }
+}
+
+impl<T: Write, const N: usize> Write for CobsTransport<T, N> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, Self::Error> {
+        if self.tx_len + buf.len() > N - CRC_LEN {
+            return Err(FramingError::FrameTooLong);
+        }
+
+        self.tx[self.tx_len..self.tx_len + buf.len()].copy_from_slice(buf);
+        self.tx_len += buf.len();
+        Ok(buf.len())
+    }
+
+    async fn flush(&mut self) -> Result<(), Self::Error> {
+        if self.tx_len > 0 {
+            let crc = crc16(&self.tx[..self.tx_len]);
+            self.tx[self.tx_len..self.tx_len + CRC_LEN].copy_from_slice(&crc.to_be_bytes());
+            let frame_len = self.tx_len + CRC_LEN;
+            // The frame is gone from the buffer even if writing it fails midway.
+            self.tx_len = 0;
+
+            let mut rest = &self.tx[..frame_len];
+            loop {
+                let max_block_len = usize::from(MAX_BLOCK_CODE - 1);
+                let block_len = rest
+                    .iter()
+                    .take(max_block_len)
+                    .position(|&b| b == 0)
+                    .unwrap_or(rest.len().min(max_block_len));
+                let (block, tail) = rest.split_at(block_len);
+
+                let code = u8::try_from(block_len + 1).expect("Block length is at most 254");
+                write_all(&mut self.inner, &[code]).await?;
+                write_all(&mut self.inner, block).await?;
+
+                match (block_len == max_block_len, tail.split_first()) {
+                    (_, None) => break,
+                    // Maximum size blocks are not followed by an encoded zero.
+                    (true, Some(_)) => rest = tail,
+                    (false, Some((_zero, tail))) => rest = tail,
+                }
+            }
+            write_all(&mut self.inner, &[DELIMITER]).await?;
+        }
+
+        self.inner.flush().await.map_err(FramingError::Transport)
+    }
+}
+
+async fn write_all<T: Write>(inner: &mut T, buf: &[u8]) -> Result<(), FramingError<T::Error>> {
+    inner.write_all(buf).await.map_err(FramingError::Transport)
+}
+
+/// CRC-16/CCITT-FALSE, with polynomial 0x1021 and initial value 0xFFFF.
+fn crc16(data: &[u8]) -> u16 {
+    let mut crc = 0xFFFFu16;
+    for &byte in data {
+        crc ^= u16::from(byte) << 8;
+        for _ in 0..8 {
+            crc = match crc & 0x8000 {
+                0 => crc << 1,
+                _ => (crc << 1) ^ 0x1021,
+            };
+        }
+    }
+    crc
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    async fn encode(payload: &[u8], buffer: &mut [u8]) -> usize {
+        let mut writer = &mut buffer[..];
+        let mut transport = CobsTransport::<_, 512>::new(&mut writer);
+        transport.write_all(payload).await.unwrap();
+        transport.flush().await.unwrap();
+        let remaining = transport.into_inner().len();
+        buffer.len() - remaining
+    }
+
+    #[test]
+    fn test_crc16() {
+        assert_eq!(crc16(b"123456789"), 0x29B1);
+        assert_eq!(crc16(&[]), 0xFFFF);
+    }
+
+    #[tokio::test]
+    async fn test_write_frame() {
+        // PINGREQ packet
+        let mut buffer = [0u8; 16];
+        let len = encode(&[0xC0, 0x00], &mut buffer).await;
+        assert_eq!(buffer[..len], [0x02, 0xC0, 0x03, 0x0B, 0x5B, 0x00]);
+    }
+
+    #[tokio::test]
+    async fn test_write_frame_too_long() {
+        let mut buffer = [0u8; 16];
+        let mut writer = &mut buffer[..];
+        let mut transport = CobsTransport::<_, 4>::new(&mut writer);
+        transport.write_all(&[1, 2]).await.unwrap();
+        let result = transport.write(&[3]).await;
+        assert!(matches!(result, Err(FramingError::FrameTooLong)));
+    }
+
+    #[tokio::test]
+    async fn test_read_frame() {
+        let data = [0x02, 0xC0, 0x03, 0x0B, 0x5B, 0x00];
+        let mut transport = CobsTransport::<_, 16>::new(&data[..]);
+
+        let mut buf = [0u8; 2];
+        transport.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf, [0xC0, 0x00]);
+        assert_eq!(transport.read(&mut buf).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_read_skips_empty_frames() {
+        let data = [0x00, 0x00, 0x02, 0xC0, 0x03, 0x0B, 0x5B, 0x00, 0x00];
+        let mut transport = CobsTransport::<_, 16>::new(&data[..]);
+
+        let mut buf = [0u8; 2];
+        transport.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf, [0xC0, 0x00]);
+        assert_eq!(transport.read(&mut buf).await.unwrap(), 0);
+    }
+
+    #[tokio::test]
+    async fn test_read_crc_mismatch() {
+        let data = [0x02, 0xC0, 0x03, 0x0B, 0x5C, 0x00];
+        let mut transport = CobsTransport::<_, 16>::new(&data[..]);
+        let mut buf = [0u8; 2];
+        let result = transport.read(&mut buf).await;
+        assert!(matches!(result, Err(FramingError::Corrupted)));
+    }
+
+    #[tokio::test]
+    async fn test_read_truncated_block() {
+        let data = [0x05, 0xC0, 0x00];
+        let mut transport = CobsTransport::<_, 16>::new(&data[..]);
+        let mut buf = [0u8; 2];
+        let result = transport.read(&mut buf).await;
+        assert!(matches!(result, Err(FramingError::Corrupted)));
+    }
+
+    #[tokio::test]
+    async fn test_read_eof_mid_frame() {
+        let data = [0x02, 0xC0, 0x03];
+        let mut transport = CobsTransport::<_, 16>::new(&data[..]);
+        let mut buf = [0u8; 2];
+        let result = transport.read(&mut buf).await;
+        assert!(matches!(result, Err(FramingError::UnexpectedEof)));
+    }
+
+    #[tokio::test]
+    async fn test_read_frame_too_long_resynchronizes() {
+        let mut buffer = [0u8; 64];
+        let first = encode(&[1, 2, 3, 4, 5, 6], &mut buffer).await;
+        let second = encode(&[7], &mut buffer[first..]).await;
+
+        let mut transport = CobsTransport::<_, 4>::new(&buffer[..first + second]);
+        let mut buf = [0u8; 1];
+        let result = transport.read(&mut buf).await;
+        assert!(matches!(result, Err(FramingError::FrameTooLong)));
+        transport.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf, [7]);
+    }
+
+    #[tokio::test]
+    async fn test_roundtrip() {
+        let mut long_run = [0xAAu8; 300];
+        long_run[254] = 0;
+        let mut exact_block = [0x55u8; 254];
+        exact_block[0] = 0x01;
+        let payloads: [&[u8]; 7] = [
+            &[0x00],
+            &[0x00, 0x00],
+            &[0x11, 0x22, 0x00, 0x33],
+            &[0x11, 0x00],
+            &[0xFF; 253],
+            &exact_block,
+            &long_run,
+        ];
+
+        for payload in payloads {
+            let mut buffer = [0u8; 512];
+            let len = encode(payload, &mut buffer).await;
+            assert_eq!(buffer[len - 1], DELIMITER);
+            assert!(!buffer[..len - 1].contains(&DELIMITER));
+
+            let mut transport = CobsTransport::<_, 512>::new(&buffer[..len]);
+            let mut decoded = [0u8; 300];
+            transport
+                .read_exact(&mut decoded[..payload.len()])
+                .await
+                .unwrap();
+            assert_eq!(&decoded[..payload.len()], payload);
+            assert_eq!(transport.read(&mut decoded).await.unwrap(), 0);
+        }
+    }
+}
@@ -0,0 +1,208 @@
+//! This module contains an in-memory transport for testing MQTT logic without a broker.
+//!
+//! The transport plays back a script of incoming bytes, errors and end of stream, and captures
+//! everything written to it.
+
+use embedded_io_async::{ErrorKind, ErrorType, Read, Write};
+
+/// An error injected by a [`MockTransport`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct MockError(pub ErrorKind);
+
+impl embedded_io_async::Error for MockError {
+    fn kind(&self) -> ErrorKind {
+        self.0
+    }
+}
+
+/// A step of the script played back by a [`MockTransport`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Step<'a> {
+    /// Bytes to return from reads. A read never returns bytes of more than one step, so
+    /// splitting data into steps controls how it is chunked.
+    Data(&'a [u8]),
+    /// Fail the next read with the given error.
+    Error(ErrorKind),
+    /// Return end of stream from the next read, even if steps follow.
+    Eof,
+}
+
+/// Transport reading from a script and capturing up to `OUT` written bytes.
+///
+/// Once the script is exhausted, reads return end of stream.
+#[derive(Debug)]
+pub struct MockTransport<'a, const OUT: usize> {
+    /// The rest of the step currently being read.
+    data: &'a [u8],
+    script: &'a [Step<'a>],
+    output: [u8; OUT],
+    output_len: usize,
+    write_error: Option<ErrorKind>,
+}
+
+impl<'a, const OUT: usize> MockTransport<'a, OUT> {
+    /// Transport returning the given bytes, followed by end of stream.
+    pub fn new(input: &'a [u8]) -> Self {
+        Self {
+            data: input,
+            script: &[],
+            output: [0; OUT],
+            output_len: 0,
+            write_error: None,
+        }
+    }
+
+    /// Transport playing back the given steps, followed by end of stream.
+    pub fn with_script(script: &'a [Step<'a>]) -> Self {
+        Self {
+            script,
+            ..Self::new(&[])
+        }
+    }
+
+    /// Fail all following writes and flushes with the given error, or stop failing them.
+    pub fn fail_writes(&mut self, error: Option<ErrorKind>) {
+        self.write_error = error;
+    }
+
+    /// Everything written so far.
+    pub fn written(&self) -> &[u8] {
+        &self.output[..self.output_len]
+    }
+
+    pub fn clear_written(&mut self) {
+        self.output_len = 0;
+    }
+
+    /// The bytes of the current step not read yet.
+    pub fn unread(&self) -> &'a [u8] {
+        self.data
+    }
+
+    /// Whether the whole script has been read.
+    pub fn is_exhausted(&self) -> bool {
+        self.data.is_empty() && self.script.is_empty()
+    }
+}
+
+impl<const OUT: usize> ErrorType for MockTransport<'_, OUT> {
+    type Error = MockError;
+}
+
+impl<const OUT: usize> Read for MockTransport<'_, OUT> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, MockError> {
+        while self.data.is_empty() {
+            let Some((step, rest)) = self.script.split_first() else {
+                return Ok(0);
+            };
+            self.script = rest;
+            match *step {
+                Step::Data(data) => self.data = data,
+                Step::Error(kind) => return Err(MockError(kind)),
+                Step::Eof => return Ok(0),
+            }
+        }
+
+        let len = buf.len().min(self.data.len());
+        buf[..len].copy_from_slice(&self.data[..len]);
+        self.data = &self.data[len..];
+        Ok(len)
+    }
+}
+
+impl<const OUT: usize> Write for MockTransport<'_, OUT> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, MockError> {
+        if let Some(kind) = self.write_error {
+            return Err(MockError(kind));
+        }
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        if self.output_len == OUT {
+            return Err(MockError(ErrorKind::OutOfMemory));
+        }
+
+        let len = buf.len().min(OUT - self.output_len);
+        self.output[self.output_len..self.output_len + len].copy_from_slice(&buf[..len]);
+        self.output_len += len;
+        Ok(len)
+    }
+
+    async fn flush(&mut self) -> Result<(), MockError> {
+        match self.write_error {
+            Some(kind) => Err(MockError(kind)),
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{error::Error, packet::fixed_header::FixedHeader};
+
+    #[tokio::test]
+    async fn test_script() {
+        let script = [
+            Step::Data(&[1, 2, 3]),
+            Step::Data(&[4]),
+            Step::Error(ErrorKind::TimedOut),
+            Step::Data(&[5]),
+            Step::Eof,
+            Step::Data(&[6]),
+        ];
+        let mut transport = MockTransport::<0>::with_script(&script);
+
+        let mut buf = [0u8; 8];
+        assert_eq!(transport.read(&mut buf[..2]).await, Ok(2));
+        assert_eq!(transport.unread(), [3]);
+        // Reads don't cross step boundaries.
+        assert_eq!(transport.read(&mut buf).await, Ok(1));
+        assert_eq!(transport.read(&mut buf).await, Ok(1));
+        assert_eq!(buf[0], 4);
+        assert_eq!(
+            transport.read(&mut buf).await,
+            Err(MockError(ErrorKind::TimedOut))
+        );
+        assert_eq!(transport.read(&mut buf).await, Ok(1));
+        assert_eq!(transport.read(&mut buf).await, Ok(0));
+        assert_eq!(transport.read(&mut buf).await, Ok(1));
+        assert!(transport.is_exhausted());
+        assert_eq!(transport.read(&mut buf).await, Ok(0));
+    }
+
+    #[tokio::test]
+    async fn test_capture_writes() {
+        let mut transport = MockTransport::<4>::new(&[]);
+        transport.write_all(&[1, 2, 3]).await.unwrap();
+        assert_eq!(transport.written(), [1, 2, 3]);
+        assert!(transport.write_all(&[4, 5]).await.is_err());
+        assert_eq!(transport.written(), [1, 2, 3, 4]);
+
+        transport.clear_written();
+        transport.fail_writes(Some(ErrorKind::ConnectionReset));
+        assert_eq!(
+            transport.write(&[1]).await,
+            Err(MockError(ErrorKind::ConnectionReset))
+        );
+        assert!(transport.flush().await.is_err());
+        transport.fail_writes(None);
+        transport.write_all(&[6]).await.unwrap();
+        assert_eq!(transport.written(), [6]);
+    }
+
+    #[tokio::test]
+    async fn test_packet_codec_over_mock() {
+        let mut transport = MockTransport::<8>::new(&[0xC0, 0x00]);
+        let header = FixedHeader::read(&mut transport).await.unwrap();
+        header.write(&mut transport).await.unwrap();
+        assert_eq!(transport.written(), [0xC0, 0x00]);
+
+        // End of stream in the middle of a packet.
+        let mut transport = MockTransport::<0>::new(&[0x30]);
+        assert!(matches!(
+            FixedHeader::read(&mut transport).await,
+            Err(Error::MalformedPacket)
+        ));
+    }
+}
@@ -0,0 +1,70 @@
+//! This module contains transports to carry MQTT packets over, adapting the I/O types of other
+//! crates to the `embedded-io-async` traits used throughout this crate.
+
+pub mod ble;
+pub mod cobs;
+#[cfg(any(test, feature = "test-util"))]
+pub mod mock;
+pub mod proxy;
+#[cfg(feature = "quic")]
+pub mod quic;
+pub mod record;
+#[cfg(feature = "tokio")]
+pub mod tokio;
+
+use embedded_io_async::{Read, Write};
+
+/// Opens new connections to the broker, so they can be re-established after a drop without
+/// knowing the details of the underlying transport.
+///
+/// This is implemented for closures returning a future, e.g. `|| tokio::connect(addr)`.
+#[allow(async_fn_in_trait)]
+pub trait TransportFactory {
+    type Transport: Read + Write;
+    type Error;
+
+    async fn connect(&mut self) -> Result<Self::Transport, Self::Error>;
+}
+
+impl<F, Fut, T, E> TransportFactory for F
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+    T: Read + Write,
+{
+    type Transport = T;
+    type Error = E;
+
+    async fn connect(&mut self) -> Result<T, E> {
+        self().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::TransportFactory;
+    use crate::transport::mock::MockTransport;
+
+    async fn reconnect<F: TransportFactory>(factory: &mut F) -> Result<F::Transport, F::Error> {
+        factory.connect().await
+    }
+
+    #[tokio::test]
+    async fn test_closure_transport_factory() {
+        let mut attempts = 0;
+        let mut factory = || {
+            attempts += 1;
+            let attempt = attempts;
+            async move {
+                match attempt {
+                    1 => Err("unreachable"),
+                    _ => Ok(MockTransport::<0>::new(b"second")),
+                }
+            }
+        };
+
+        assert!(matches!(reconnect(&mut factory).await, Err("unreachable")));
+        let transport = reconnect(&mut factory).await.unwrap();
+        assert_eq!(transport.unread(), b"second");
+    }
+}
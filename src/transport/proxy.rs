@@ -0,0 +1,504 @@
+//! This module contains handshakes for opening a connection through a SOCKS5 or HTTP proxy.
+//!
+//! Each handshake is performed on a stream that is already connected to the proxy. On success,
+//! the stream is handed back and carries the connection to the target broker.
+
+use crate::packet::data_representation;
+use core::net::{Ipv4Addr, Ipv6Addr};
+use embedded_io_async::{Read, ReadExactError, Write};
+
+const SOCKS_VERSION: u8 = 0x05;
+const SOCKS_METHOD_NO_AUTH: u8 = 0x00;
+const SOCKS_METHOD_USERNAME_PASSWORD: u8 = 0x02;
+const SOCKS_METHOD_NONE_ACCEPTABLE: u8 = 0xFF;
+const SOCKS_USERNAME_PASSWORD_VERSION: u8 = 0x01;
+const SOCKS_COMMAND_CONNECT: u8 = 0x01;
+const SOCKS_ADDRESS_IPV4: u8 = 0x01;
+const SOCKS_ADDRESS_DOMAIN: u8 = 0x03;
+const SOCKS_ADDRESS_IPV6: u8 = 0x04;
+const SOCKS_REPLY_SUCCEEDED: u8 = 0x00;
+
+/// Upper bound for the HTTP response to a CONNECT request, to avoid reading forever from a
+/// misbehaving proxy.
+const HTTP_MAX_RESPONSE_LEN: usize = 4096;
+
+#[derive(Debug)]
+pub enum ProxyError<E> {
+    /// The target host name or the credentials can't be encoded in the handshake.
+    InvalidRequest,
+    /// The proxy refused the authentication or the connection to the target.
+    Rejected,
+    /// The proxy sent a response that does not follow the protocol.
+    InvalidResponse,
+    NetworkError(E),
+}
+
+impl<E> From<ReadExactError<E>> for ProxyError<E> {
+    fn from(value: ReadExactError<E>) -> Self {
+        match value {
+            // Connection was closed in the middle of the handshake.
+            ReadExactError::UnexpectedEof => ProxyError::InvalidResponse,
+            ReadExactError::Other(e) => ProxyError::NetworkError(e),
+        }
+    }
+}
+
+impl<E> From<crate::error::Error<E>> for ProxyError<E> {
+    fn from(value: crate::error::Error<E>) -> Self {
+        match value {
+            crate::error::Error::MalformedPacket => ProxyError::InvalidResponse,
+            crate::error::Error::NetworkError(e) => ProxyError::NetworkError(e),
+        }
+    }
+}
+
+/// Ask a SOCKS5 proxy to connect to the given host and port, following RFC 1928.
+///
+/// IPv4 and IPv6 addresses are sent as such, so the proxy doesn't try to resolve them. IPv6
+/// addresses may be given with or without brackets.
+///
+/// If credentials are given, the proxy may authenticate using username and password
+/// (RFC 1929). Otherwise only unauthenticated access is offered.
+pub async fn socks5_connect<T: Read + Write>(
+    mut stream: T,
+    host: &str,
+    port: u16,
+    credentials: Option<(&str, &str)>,
+) -> Result<T, ProxyError<T::Error>> {
+    let host_len: u8 = match host.len() {
+        0 => return Err(ProxyError::InvalidRequest),
+        len => len.try_into().map_err(|_| ProxyError::InvalidRequest)?,
+    };
+
+    let greeting: &[u8] = match credentials {
+        Some(_) => &[
+            SOCKS_VERSION,
+            2,
+            SOCKS_METHOD_NO_AUTH,
+            SOCKS_METHOD_USERNAME_PASSWORD,
+        ],
+        None => &[SOCKS_VERSION, 1, SOCKS_METHOD_NO_AUTH],
+    };
+    write_all(&mut stream, greeting).await?;
+    stream.flush().await.map_err(ProxyError::NetworkError)?;
+
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != SOCKS_VERSION {
+        return Err(ProxyError::InvalidResponse);
+    }
+    match (reply[1], credentials) {
+        (SOCKS_METHOD_NO_AUTH, _) => {}
+        (SOCKS_METHOD_USERNAME_PASSWORD, Some((username, password))) => {
+            socks5_authenticate(&mut stream, username, password).await?
+        }
+        (SOCKS_METHOD_NONE_ACCEPTABLE, _) => return Err(ProxyError::Rejected),
+        _ => return Err(ProxyError::InvalidResponse),
+    }
+
+    write_all(&mut stream, &[SOCKS_VERSION, SOCKS_COMMAND_CONNECT, 0x00]).await?;
+    let unbracketed = host
+        .strip_prefix('[')
+        .and_then(|host| host.strip_suffix(']'))
+        .unwrap_or(host);
+    if let Ok(address) = host.parse::<Ipv4Addr>() {
+        write_all(&mut stream, &[SOCKS_ADDRESS_IPV4]).await?;
+        write_all(&mut stream, &address.octets()).await?;
+    } else if let Ok(address) = unbracketed.parse::<Ipv6Addr>() {
+        write_all(&mut stream, &[SOCKS_ADDRESS_IPV6]).await?;
+        write_all(&mut stream, &address.octets()).await?;
+    } else {
+        write_all(&mut stream, &[SOCKS_ADDRESS_DOMAIN, host_len]).await?;
+        write_all(&mut stream, host.as_bytes()).await?;
+    }
+    data_representation::write_u16(port, &mut stream).await?;
+    stream.flush().await.map_err(ProxyError::NetworkError)?;
+
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != SOCKS_VERSION {
+        return Err(ProxyError::InvalidResponse);
+    }
+    if reply[1] != SOCKS_REPLY_SUCCEEDED {
+        return Err(ProxyError::Rejected);
+    }
+
+    // The reply ends with the address the proxy bound to, which we don't need.
+    let address_len = match reply[3] {
+        SOCKS_ADDRESS_IPV4 => 4,
+        SOCKS_ADDRESS_DOMAIN => usize::from(data_representation::read_u8(&mut stream).await?),
+        SOCKS_ADDRESS_IPV6 => 16,
+        _ => return Err(ProxyError::InvalidResponse),
+    };
+    // Skip the address and the port.
+    for _ in 0..address_len + 2 {
+        data_representation::read_u8(&mut stream).await?;
+    }
+
+    Ok(stream)
+}
+
+async fn socks5_authenticate<T: Read + Write>(
+    stream: &mut T,
+    username: &str,
+    password: &str,
+) -> Result<(), ProxyError<T::Error>> {
+    let username_len: u8 = username
+        .len()
+        .try_into()
+        .map_err(|_| ProxyError::InvalidRequest)?;
+    let password_len: u8 = password
+        .len()
+        .try_into()
+        .map_err(|_| ProxyError::InvalidRequest)?;
+
+    write_all(stream, &[SOCKS_USERNAME_PASSWORD_VERSION, username_len]).await?;
+    write_all(stream, username.as_bytes()).await?;
+    write_all(stream, &[password_len]).await?;
+    write_all(stream, password.as_bytes()).await?;
+    stream.flush().await.map_err(ProxyError::NetworkError)?;
+
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != SOCKS_USERNAME_PASSWORD_VERSION {
+        return Err(ProxyError::InvalidResponse);
+    }
+    if reply[1] != SOCKS_REPLY_SUCCEEDED {
+        return Err(ProxyError::Rejected);
+    }
+
+    Ok(())
+}
+
+/// Ask an HTTP proxy to open a tunnel to the given host and port using the CONNECT method.
+///
+/// IPv6 addresses may be given with or without brackets.
+///
+/// If given, `proxy_authorization` is sent as the value of the `Proxy-Authorization` header,
+/// e.g. `Basic dXNlcjpwYXNz`.
+pub async fn http_connect<T: Read + Write>(
+    mut stream: T,
+    host: &str,
+    port: u16,
+    proxy_authorization: Option<&str>,
+) -> Result<T, ProxyError<T::Error>> {
+    if host.is_empty() || host.contains(['\r', '\n', ' ']) {
+        return Err(ProxyError::InvalidRequest);
+    }
+    if proxy_authorization.is_some_and(|auth| auth.contains(['\r', '\n'])) {
+        return Err(ProxyError::InvalidRequest);
+    }
+
+    let mut port_buf = [0u8; 5];
+    let port = format_port(port, &mut port_buf);
+    // IPv6 literals must be bracketed to separate them from the port.
+    let (open, close) = match host.contains(':') && !host.starts_with('[') {
+        true => ("[", "]"),
+        false => ("", ""),
+    };
+
+    for part in [
+        "CONNECT ",
+        open,
+        host,
+        close,
+        ":",
+        port,
+        " HTTP/1.1\r\nHost: ",
+        open,
+        host,
+        close,
+        ":",
+        port,
+        "\r\n",
+    ] {
+        write_all(&mut stream, part.as_bytes()).await?;
+    }
+    if let Some(auth) = proxy_authorization {
+        for part in ["Proxy-Authorization: ", auth, "\r\n"] {
+            write_all(&mut stream, part.as_bytes()).await?;
+        }
+    }
+    write_all(&mut stream, b"\r\n").await?;
+    stream.flush().await.map_err(ProxyError::NetworkError)?;
+
+    // The status line looks like "HTTP/1.1 200 Connection established". Only keep the part up
+    // to and including the status code.
+    let mut status_line = [0u8; 12];
+    stream.read_exact(&mut status_line).await?;
+    if !status_line.starts_with(b"HTTP/1.") || status_line[8] != b' ' {
+        return Err(ProxyError::InvalidResponse);
+    }
+    let status_ok = &status_line[9..12] == b"200";
+
+    // Read byte by byte up to the empty line ending the headers, so no bytes belonging to the
+    // tunneled connection are consumed.
+    let mut last_four = [0u8; 4];
+    let mut read = status_line.len();
+    while &last_four != b"\r\n\r\n" {
+        if read >= HTTP_MAX_RESPONSE_LEN {
+            return Err(ProxyError::InvalidResponse);
+        }
+        last_four.rotate_left(1);
+        last_four[3] = data_representation::read_u8(&mut stream).await?;
+        read += 1;
+    }
+
+    match status_ok {
+        true => Ok(stream),
+        false => Err(ProxyError::Rejected),
+    }
+}
+
+fn format_port(port: u16, buf: &mut [u8; 5]) -> &str {
+    let mut start = buf.len();
+    let mut remaining = port;
+    loop {
+        start -= 1;
+        buf[start] = b'0' + (remaining % 10) as u8;
+        remaining /= 10;
+        if remaining == 0 {
+            break;
+        }
+    }
+    core::str::from_utf8(&buf[start..]).expect("ASCII digits are valid UTF-8")
+}
+
+async fn write_all<T: Write>(stream: &mut T, bytes: &[u8]) -> Result<(), ProxyError<T::Error>> {
+    stream
+        .write_all(bytes)
+        .await
+        .map_err(ProxyError::NetworkError)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::mock::MockTransport;
+
+    /// Stream that reads from a scripted proxy response and captures everything written.
+    type Scripted<'a> = MockTransport<'a, 128>;
+
+    #[test]
+    fn test_format_port() {
+        let mut buf = [0u8; 5];
+        assert_eq!(format_port(0, &mut buf), "0");
+        assert_eq!(format_port(1883, &mut buf), "1883");
+        assert_eq!(format_port(65535, &mut buf), "65535");
+    }
+
+    #[tokio::test]
+    async fn test_socks5_connect_no_auth() {
+        let response = [
+            0x05, 0x00, // Method selection
+            0x05, 0x00, 0x00, 0x01, 10, 0, 0, 1, 0x07, 0x5B, // Connect reply, IPv4
+            0x10, // First byte of the tunneled connection
+        ];
+        let stream = Scripted::new(&response);
+
+        let stream = socks5_connect(stream, "broker", 1883, None).await.unwrap();
+        assert_eq!(
+            stream.written(),
+            [
+                0x05, 0x01, 0x00, // Greeting
+                0x05, 0x01, 0x00, 0x03, 6, b'b', b'r', b'o', b'k', b'e', b'r', 0x07,
+                0x5B, // Connect request
+            ]
+        );
+        assert_eq!(stream.unread(), [0x10]);
+    }
+
+    #[tokio::test]
+    async fn test_socks5_connect_ipv4() {
+        let response = [0x05, 0x00, 0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0];
+        let stream = socks5_connect(Scripted::new(&response), "192.168.0.1", 1883, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            stream.written(),
+            [
+                0x05, 0x01, 0x00, // Greeting
+                0x05, 0x01, 0x00, 0x01, 192, 168, 0, 1, 0x07, 0x5B, // Connect request
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn test_socks5_connect_ipv6() {
+        let response = [0x05, 0x00, 0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0];
+
+        for host in ["2001:db8::1", "[2001:db8::1]"] {
+            let stream = socks5_connect(Scripted::new(&response), host, 1883, None)
+                .await
+                .unwrap();
+            assert_eq!(
+                stream.written(),
+                [
+                    0x05, 0x01, 0x00, // Greeting
+                    0x05, 0x01, 0x00, 0x04, 0x20, 0x01, 0x0D, 0xB8, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+                    0, 1, 0x07, 0x5B, // Connect request
+                ]
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_socks5_connect_username_password() {
+        let response = [
+            0x05, 0x02, // Method selection
+            0x01, 0x00, // Authentication succeeded
+            0x05, 0x00, 0x00, 0x03, 2, b'p', b'x', 0x00, 0x01, // Connect reply, domain
+        ];
+        let stream = Scripted::new(&response);
+
+        let stream = socks5_connect(stream, "b", 1, Some(("u", "pw")))
+            .await
+            .unwrap();
+        assert_eq!(
+            stream.written(),
+            [
+                0x05, 0x02, 0x00, 0x02, // Greeting
+                0x01, 1, b'u', 2, b'p', b'w', // Authentication
+                0x05, 0x01, 0x00, 0x03, 1, b'b', 0x00, 0x01, // Connect request
+            ]
+        );
+        assert!(stream.is_exhausted());
+    }
+
+    #[tokio::test]
+    async fn test_socks5_connect_authentication_failed() {
+        let response = [0x05, 0x02, 0x01, 0x01];
+        let stream = Scripted::new(&response);
+        let result = socks5_connect(stream, "b", 1, Some(("u", "pw"))).await;
+        assert!(matches!(result, Err(ProxyError::Rejected)));
+    }
+
+    #[tokio::test]
+    async fn test_socks5_connect_no_acceptable_method() {
+        let response = [0x05, 0xFF];
+        let stream = Scripted::new(&response);
+        let result = socks5_connect(stream, "b", 1, None).await;
+        assert!(matches!(result, Err(ProxyError::Rejected)));
+    }
+
+    #[tokio::test]
+    async fn test_socks5_connect_unrequested_method() {
+        // Proxy picks username/password although we offered no credentials.
+        let response = [0x05, 0x02];
+        let stream = Scripted::new(&response);
+        let result = socks5_connect(stream, "b", 1, None).await;
+        assert!(matches!(result, Err(ProxyError::InvalidResponse)));
+    }
+
+    #[tokio::test]
+    async fn test_socks5_connect_refused() {
+        // Connection refused
+        let response = [0x05, 0x00, 0x05, 0x05, 0x00, 0x01, 0, 0, 0, 0, 0, 0];
+        let stream = Scripted::new(&response);
+        let result = socks5_connect(stream, "b", 1, None).await;
+        assert!(matches!(result, Err(ProxyError::Rejected)));
+    }
+
+    #[tokio::test]
+    async fn test_socks5_connect_truncated_reply() {
+        let response = [0x05, 0x00, 0x05, 0x00, 0x00, 0x01, 0, 0];
+        let stream = Scripted::new(&response);
+        let result = socks5_connect(stream, "b", 1, None).await;
+        assert!(matches!(result, Err(ProxyError::InvalidResponse)));
+    }
+
+    #[tokio::test]
+    async fn test_socks5_connect_invalid_host() {
+        let result = socks5_connect(Scripted::new(&[]), "", 1, None).await;
+        assert!(matches!(result, Err(ProxyError::InvalidRequest)));
+
+        let host = "a".repeat(256);
+        let result = socks5_connect(Scripted::new(&[]), &host, 1, None).await;
+        assert!(matches!(result, Err(ProxyError::InvalidRequest)));
+    }
+
+    #[tokio::test]
+    async fn test_http_connect_success() {
+        let response = b"HTTP/1.1 200 Connection established\r\nVia: proxy\r\n\r\n\x10";
+        let stream = Scripted::new(response);
+
+        let stream = http_connect(stream, "broker.example.com", 8883, None)
+            .await
+            .unwrap();
+        assert_eq!(
+            stream.written(),
+            b"CONNECT broker.example.com:8883 HTTP/1.1\r\nHost: broker.example.com:8883\r\n\r\n"
+        );
+        assert_eq!(stream.unread(), [0x10]);
+    }
+
+    #[tokio::test]
+    async fn test_http_connect_ipv6() {
+        let response = b"HTTP/1.1 200 OK\r\n\r\n";
+
+        for host in ["::1", "[::1]"] {
+            let stream = http_connect(Scripted::new(response), host, 1883, None)
+                .await
+                .unwrap();
+            assert_eq!(
+                stream.written(),
+                b"CONNECT [::1]:1883 HTTP/1.1\r\nHost: [::1]:1883\r\n\r\n"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn test_http_connect_authorization() {
+        let response = b"HTTP/1.0 200 OK\r\n\r\n";
+        let stream = Scripted::new(response);
+
+        let stream = http_connect(stream, "b", 1883, Some("Basic dTpw"))
+            .await
+            .unwrap();
+        assert_eq!(
+            stream.written(),
+            b"CONNECT b:1883 HTTP/1.1\r\nHost: b:1883\r\nProxy-Authorization: Basic dTpw\r\n\r\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_http_connect_rejected() {
+        let response = b"HTTP/1.1 407 Proxy Authentication Required\r\n\r\n";
+        let result = http_connect(Scripted::new(response), "b", 1, None).await;
+        assert!(matches!(result, Err(ProxyError::Rejected)));
+    }
+
+    #[tokio::test]
+    async fn test_http_connect_invalid_response() {
+        let response = b"SSH-2.0-OpenSSH\r\n\r\n";
+        let result = http_connect(Scripted::new(response), "b", 1, None).await;
+        assert!(matches!(result, Err(ProxyError::InvalidResponse)));
+
+        // Headers never end.
+        let response = b"HTTP/1.1 200 OK\r\n";
+        let result = http_connect(Scripted::new(response), "b", 1, None).await;
+        assert!(matches!(result, Err(ProxyError::InvalidResponse)));
+    }
+
+    #[tokio::test]
+    async fn test_http_connect_response_too_long() {
+        let mut response = b"HTTP/1.1 200 OK\r\nVia: ".to_vec();
+        response.resize(HTTP_MAX_RESPONSE_LEN + 100, b'a');
+        response.extend_from_slice(b"\r\n\r\n");
+
+        let mut stream = Scripted::new(&response);
+        let result = http_connect(&mut stream, "b", 1, None).await;
+        assert!(matches!(result, Err(ProxyError::InvalidResponse)));
+        // Reading stopped at the limit.
+        assert_eq!(
+            stream.unread().len(),
+            response.len() - HTTP_MAX_RESPONSE_LEN
+        );
+    }
+
+    #[tokio::test]
+    async fn test_http_connect_invalid_host() {
+        let result = http_connect(Scripted::new(&[]), "b\r\nX: y", 1, None).await;
+        assert!(matches!(result, Err(ProxyError::InvalidRequest)));
+    }
+}
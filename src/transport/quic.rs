@@ -0,0 +1,91 @@
+//! This module contains an experimental adapter for carrying MQTT over QUIC streams, as offered
+//! by brokers such as EMQX.
+//!
+//! MQTT is carried over a bidirectional QUIC stream just like over a TCP connection, so the
+//! stream is used as transport as is. The QUIC implementation itself is provided by the
+//! application through [`QuicConnection`].
+
+use embedded_io_async::{Read, Write};
+
+use super::TransportFactory;
+
+/// An established QUIC connection to the broker.
+#[allow(async_fn_in_trait)]
+pub trait QuicConnection {
+    type Stream: Read + Write;
+    type Error;
+
+    /// Open a new bidirectional stream.
+    async fn open_bi(&mut self) -> Result<Self::Stream, Self::Error>;
+}
+
+/// Transport factory opening a new stream on the QUIC connection for each MQTT connection.
+///
+/// As long as the QUIC connection survives, e.g. across a change of network address, a new MQTT
+/// connection is established without a new handshake.
+#[derive(Debug)]
+pub struct QuicTransportFactory<C> {
+    connection: C,
+}
+
+impl<C: QuicConnection> QuicTransportFactory<C> {
+    pub fn new(connection: C) -> Self {
+        Self { connection }
+    }
+
+    pub fn into_inner(self) -> C {
+        self.connection
+    }
+}
+
+impl<C: QuicConnection> TransportFactory for QuicTransportFactory<C> {
+    type Transport = C::Stream;
+    type Error = C::Error;
+
+    async fn connect(&mut self) -> Result<C::Stream, C::Error> {
+        self.connection.open_bi().await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::mock::MockTransport;
+
+    /// Hands out client-initiated bidirectional stream ids, failing once the limit is reached.
+    /// Each stream reads the data the broker sends on it.
+    struct Connection {
+        next_stream_id: u64,
+        streams: &'static [&'static [u8]],
+    }
+
+    impl QuicConnection for Connection {
+        type Stream = MockTransport<'static, 0>;
+        type Error = &'static str;
+
+        async fn open_bi(&mut self) -> Result<Self::Stream, Self::Error> {
+            let data = self
+                .streams
+                .get((self.next_stream_id / 4) as usize)
+                .ok_or("stream limit reached")?;
+            self.next_stream_id += 4;
+            Ok(MockTransport::new(data))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_connect_opens_new_stream() {
+        let mut factory = QuicTransportFactory::new(Connection {
+            next_stream_id: 0,
+            streams: &[b"stream 0", b"stream 4"],
+        });
+
+        assert_eq!(factory.connect().await.unwrap().unread(), b"stream 0");
+        assert_eq!(factory.connect().await.unwrap().unread(), b"stream 4");
+        assert!(matches!(
+            factory.connect().await,
+            Err("stream limit reached")
+        ));
+        assert_eq!(factory.into_inner().next_stream_id, 8);
+    }
+}
@@ -0,0 +1,378 @@
+//! This module contains a transport wrapper recording all traffic of a session, and a transport
+//! replaying such a recording as the peer, for regression tests against captured broker behavior.
+//!
+//! A recording is a sequence of records, each consisting of a direction byte (0 for incoming,
+//! 1 for outgoing), the length of the data as a big endian `u16` and the data itself. Every read
+//! or write on the recorded transport produces one record.
+
+use crate::packet::data_representation::{take, take_u8, take_u16};
+use embedded_io_async::{ErrorKind, ErrorType, Read, Write};
+
+const INCOMING: u8 = 0;
+const OUTGOING: u8 = 1;
+const RECORD_HEADER_LEN: usize = 3;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Direction {
+    /// Read from the peer.
+    Incoming,
+    /// Written to the peer.
+    Outgoing,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Record<'a> {
+    pub direction: Direction,
+    pub data: &'a [u8],
+}
+
+/// The recording is truncated or contains a record with an unknown direction.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct InvalidRecording;
+
+/// Iterator over the records of a recording.
+///
+/// If the recording is invalid, the iterator yields [`InvalidRecording`] once and then stops.
+#[derive(Debug, Clone)]
+pub struct Records<'a> {
+    recording: &'a [u8],
+}
+
+impl<'a> Records<'a> {
+    pub fn new(recording: &'a [u8]) -> Self {
+        Self { recording }
+    }
+
+    fn take_record(&mut self) -> Result<Record<'a>, InvalidRecording> {
+        let direction = match take_u8(&mut self.recording).map_err(|_| InvalidRecording)? {
+            INCOMING => Direction::Incoming,
+            OUTGOING => Direction::Outgoing,
+            _ => return Err(InvalidRecording),
+        };
+        let len = take_u16(&mut self.recording).map_err(|_| InvalidRecording)?;
+        let data = take(&mut self.recording, usize::from(len)).map_err(|_| InvalidRecording)?;
+        Ok(Record { direction, data })
+    }
+}
+
+impl<'a> Iterator for Records<'a> {
+    type Item = Result<Record<'a>, InvalidRecording>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.recording.is_empty() {
+            return None;
+        }
+
+        let result = self.take_record();
+        if result.is_err() {
+            self.recording = &[];
+        }
+        Some(result)
+    }
+}
+
+/// Transport recording everything read from and written to the inner transport into a caller
+/// buffer.
+///
+/// Once a record doesn't fit into the buffer, recording stops, so the recording is always a
+/// complete prefix of the session. See [`Recorder::is_truncated`].
+#[derive(Debug)]
+pub struct Recorder<'b, T> {
+    inner: T,
+    buf: &'b mut [u8],
+    len: usize,
+    truncated: bool,
+}
+
+impl<'b, T> Recorder<'b, T> {
+    pub fn new(inner: T, buf: &'b mut [u8]) -> Self {
+        Self {
+            inner,
+            buf,
+            len: 0,
+            truncated: false,
+        }
+    }
+
+    /// The records of the session so far, in the format read by [`Records`] and [`Replayer`].
+    pub fn recording(&self) -> &[u8] {
+        &self.buf[..self.len]
+    }
+
+    /// Whether records were dropped because the buffer was full.
+    pub fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    pub fn into_inner(self) -> T {
+        self.inner
+    }
+
+    fn record(&mut self, direction: u8, data: &[u8]) {
+        for chunk in data.chunks(usize::from(u16::MAX)) {
+            if self.truncated || self.buf.len() - self.len < RECORD_HEADER_LEN + chunk.len() {
+                self.truncated = true;
+                return;
+            }
+
+            let len = (chunk.len() as u16).to_be_bytes();
+            let record = &mut self.buf[self.len..self.len + RECORD_HEADER_LEN + chunk.len()];
+            record[0] = direction;
+            record[1..RECORD_HEADER_LEN].copy_from_slice(&len);
+            record[RECORD_HEADER_LEN..].copy_from_slice(chunk);
+            self.len += record.len();
+        }
+    }
+}
+
+impl<T: ErrorType> ErrorType for Recorder<'_, T> {
+    type Error = T::Error;
+}
+
+impl<T: Read> Read for Recorder<'_, T> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, T::Error> {
+        let len = self.inner.read(buf).await?;
+        if len > 0 {
+            self.record(INCOMING, &buf[..len]);
+        }
+        Ok(len)
+    }
+}
+
+impl<T: Write> Write for Recorder<'_, T> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, T::Error> {
+        let len = self.inner.write(buf).await?;
+        if len > 0 {
+            self.record(OUTGOING, &buf[..len]);
+        }
+        Ok(len)
+    }
+
+    async fn flush(&mut self) -> Result<(), T::Error> {
+        self.inner.flush().await
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplayError {
+    /// The client did not behave as recorded: it wrote different bytes, wrote while the peer
+    /// was to send, or read while it was to write.
+    Diverged,
+    /// The recording is malformed.
+    InvalidRecording,
+}
+
+impl From<InvalidRecording> for ReplayError {
+    fn from(_: InvalidRecording) -> Self {
+        ReplayError::InvalidRecording
+    }
+}
+
+impl embedded_io_async::Error for ReplayError {
+    fn kind(&self) -> ErrorKind {
+        match self {
+            ReplayError::Diverged => ErrorKind::InvalidInput,
+            ReplayError::InvalidRecording => ErrorKind::InvalidData,
+        }
+    }
+}
+
+/// Transport acting as the peer of a recorded session.
+///
+/// Reads return the incoming data of the recording, and writes must match its outgoing data in
+/// the recorded order. Once the recording is finished, reads return end of stream.
+#[derive(Debug)]
+pub struct Replayer<'a> {
+    records: Records<'a>,
+    current: Option<Record<'a>>,
+}
+
+impl<'a> Replayer<'a> {
+    pub fn new(recording: &'a [u8]) -> Self {
+        Self {
+            records: Records::new(recording),
+            current: None,
+        }
+    }
+
+    /// Whether all records have been replayed.
+    pub fn is_finished(&self) -> bool {
+        self.current.is_none_or(|record| record.data.is_empty())
+            && self.records.recording.is_empty()
+    }
+
+    /// The record being replayed, with the data not replayed yet, or `None` at the end.
+    fn current(&mut self) -> Result<Option<&mut Record<'a>>, ReplayError> {
+        // Skip empty records, which the recorder never produces.
+        while self.current.is_none_or(|record| record.data.is_empty()) {
+            self.current = self.records.next().transpose()?;
+            if self.current.is_none() {
+                break;
+            }
+        }
+        Ok(self.current.as_mut())
+    }
+}
+
+impl ErrorType for Replayer<'_> {
+    type Error = ReplayError;
+}
+
+impl Read for Replayer<'_> {
+    async fn read(&mut self, buf: &mut [u8]) -> Result<usize, ReplayError> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let Some(record) = self.current()? else {
+            return Ok(0);
+        };
+        if record.direction != Direction::Incoming {
+            return Err(ReplayError::Diverged);
+        }
+
+        let len = buf.len().min(record.data.len());
+        buf[..len].copy_from_slice(&record.data[..len]);
+        record.data = &record.data[len..];
+        Ok(len)
+    }
+}
+
+impl Write for Replayer<'_> {
+    async fn write(&mut self, buf: &[u8]) -> Result<usize, ReplayError> {
+        if buf.is_empty() {
+            return Ok(0);
+        }
+        let record = match self.current()? {
+            Some(record) if record.direction == Direction::Outgoing => record,
+            _ => return Err(ReplayError::Diverged),
+        };
+
+        let len = buf.len().min(record.data.len());
+        if buf[..len] != record.data[..len] {
+            return Err(ReplayError::Diverged);
+        }
+        record.data = &record.data[len..];
+        Ok(len)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::mock::MockTransport;
+
+    const PINGREQ: [u8; 2] = [0xC0, 0x00];
+    const PINGRESP: [u8; 2] = [0xD0, 0x00];
+
+    #[tokio::test]
+    async fn test_record_session() {
+        let mut buf = [0u8; 64];
+        let mut recorder = Recorder::new(MockTransport::<8>::new(&PINGRESP), &mut buf);
+
+        recorder.write_all(&PINGREQ).await.unwrap();
+        let mut response = [0u8; 2];
+        recorder.read_exact(&mut response).await.unwrap();
+        assert!(!recorder.is_truncated());
+
+        let records: Vec<_> = Records::new(recorder.recording())
+            .map(Result::unwrap)
+            .collect();
+        assert_eq!(
+            records,
+            [
+                Record {
+                    direction: Direction::Outgoing,
+                    data: &PINGREQ
+                },
+                Record {
+                    direction: Direction::Incoming,
+                    data: &PINGRESP
+                },
+            ]
+        );
+        assert_eq!(recorder.into_inner().written(), PINGREQ);
+    }
+
+    #[tokio::test]
+    async fn test_record_truncated() {
+        let mut buf = [0u8; 6];
+        let mut recorder = Recorder::new(MockTransport::<8>::new(&[]), &mut buf);
+        recorder.write_all(&[1, 2, 3]).await.unwrap();
+        recorder.write_all(&[4]).await.unwrap();
+        assert!(recorder.is_truncated());
+        assert_eq!(recorder.recording(), [OUTGOING, 0, 3, 1, 2, 3]);
+
+        // Later records would fit, but are dropped to keep the recording consistent.
+        let mut buf = [0u8; 8];
+        let mut recorder = Recorder::new(MockTransport::<8>::new(&[]), &mut buf);
+        recorder.write_all(&[1, 2, 3, 4, 5, 6]).await.unwrap();
+        recorder.write_all(&[7]).await.unwrap();
+        assert!(recorder.is_truncated());
+        assert_eq!(recorder.recording(), []);
+    }
+
+    #[tokio::test]
+    async fn test_replay_recorded_session() {
+        let mut buf = [0u8; 64];
+        let mut recorder = Recorder::new(MockTransport::<8>::new(&PINGRESP), &mut buf);
+        recorder.write_all(&PINGREQ).await.unwrap();
+        recorder.read_exact(&mut [0u8; 2]).await.unwrap();
+
+        let mut replayer = Replayer::new(recorder.recording());
+        // Writes may be split differently than recorded.
+        replayer.write_all(&PINGREQ[..1]).await.unwrap();
+        replayer.write_all(&PINGREQ[1..]).await.unwrap();
+        let mut response = [0u8; 2];
+        replayer.read_exact(&mut response).await.unwrap();
+        assert_eq!(response, PINGRESP);
+        assert!(replayer.is_finished());
+        assert_eq!(replayer.read(&mut response).await, Ok(0));
+    }
+
+    #[tokio::test]
+    async fn test_replay_diverged() {
+        let recording = [OUTGOING, 0, 2, 0xC0, 0x00, INCOMING, 0, 2, 0xD0, 0x00];
+
+        let mut replayer = Replayer::new(&recording);
+        assert_eq!(
+            replayer.write(&[0xE0, 0x00]).await,
+            Err(ReplayError::Diverged)
+        );
+
+        let mut replayer = Replayer::new(&recording);
+        assert_eq!(
+            replayer.read(&mut [0u8; 2]).await,
+            Err(ReplayError::Diverged)
+        );
+
+        let mut replayer = Replayer::new(&recording);
+        replayer.write_all(&PINGREQ).await.unwrap();
+        assert_eq!(replayer.write(&PINGREQ).await, Err(ReplayError::Diverged));
+    }
+
+    #[test]
+    fn test_records_invalid_recording() {
+        let mut records = Records::new(&[OUTGOING, 0, 1, 0xC0, 2, 0, 0]);
+        assert!(records.next().unwrap().is_ok());
+        assert_eq!(records.next(), Some(Err(InvalidRecording)));
+        assert_eq!(records.next(), None);
+
+        let mut records = Records::new(&[INCOMING, 0]);
+        assert_eq!(records.next(), Some(Err(InvalidRecording)));
+    }
+
+    #[tokio::test]
+    async fn test_replay_invalid_recording() {
+        let mut replayer = Replayer::new(&[2, 0, 0]);
+        assert_eq!(
+            replayer.read(&mut [0u8; 1]).await,
+            Err(ReplayError::InvalidRecording)
+        );
+
+        let mut replayer = Replayer::new(&[INCOMING, 0, 3, 1]);
+        assert_eq!(
+            replayer.read(&mut [0u8; 1]).await,
+            Err(ReplayError::InvalidRecording)
+        );
+    }
+}
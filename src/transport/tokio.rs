@@ -0,0 +1,46 @@
+//! This module provides a transport over tokio's [`TcpStream`], for host-side tools and tests.
+
+pub use embedded_io_adapters::tokio_1::FromTokio;
+use tokio::net::{TcpStream, ToSocketAddrs};
+
+pub type TokioTransport = FromTokio<TcpStream>;
+
+/// Open a TCP connection to the given address.
+pub async fn connect<A: ToSocketAddrs>(addr: A) -> std::io::Result<TokioTransport> {
+    let stream = TcpStream::connect(addr).await?;
+    // MQTT packets are small and latency sensitive, so don't wait to coalesce them.
+    stream.set_nodelay(true)?;
+    Ok(FromTokio::new(stream))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::packet::data_representation;
+    use tokio::{
+        io::{AsyncReadExt, AsyncWriteExt},
+        net::TcpListener,
+    };
+
+    #[tokio::test]
+    async fn test_connect_roundtrip() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+
+        let mut transport = connect(addr).await.unwrap();
+        let (mut peer, _) = listener.accept().await.unwrap();
+
+        data_representation::write_u16(0x1234, &mut transport)
+            .await
+            .unwrap();
+        let mut buf = [0u8; 2];
+        peer.read_exact(&mut buf).await.unwrap();
+        assert_eq!(buf, [0x12, 0x34]);
+
+        peer.write_all(&[0x80, 0x01]).await.unwrap();
+        let value = data_representation::read_variable_byte_integer(&mut transport)
+            .await
+            .unwrap();
+        assert_eq!(value, 128);
+    }
+}